@@ -0,0 +1,28 @@
+// Sky dome centered on the camera. The dome normal is the view direction, the
+// color goes from the horizon color up to the zenith, with a glow and a disk
+// around the sun.
+
+#import bevy_pbr::forward_io::VertexOutput
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var<uniform> horizon: vec4<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var<uniform> zenith: vec4<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var<uniform> glow: vec4<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var<uniform> sun: vec4<f32>;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(in.world_normal);
+    // Below the horizon stays at the horizon color, like the fog
+    let up = clamp(dir.y, 0.0, 1.0);
+    var color = mix(horizon.rgb, zenith.rgb, sqrt(up));
+
+    // Sunrise and sunset glow, strongest low on the sun's side of the sky
+    let toward_sun = max(dot(dir, sun.xyz), 0.0);
+    color = mix(color, glow.rgb, glow.a * pow(toward_sun, 4.0) * (1.0 - up));
+
+    // Sun disk with a soft halo
+    let disk = smoothstep(0.9990, 0.9995, toward_sun) + 0.3 * pow(toward_sun, 200.0);
+    color += vec3(1.0, 0.95, 0.85) * disk * sun.w;
+
+    return vec4(color, 1.0);
+}
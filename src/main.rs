@@ -5,7 +5,15 @@ use bevy::window::{ExitCondition, PresentMode, WindowMode};
 use crate::world::ChunkPlugin::ChunkPlugin;
 use crate::utils::light::LightPlugin;
 use crate::utils::camera::SimpleCameraPlugin;
+use crate::utils::block_edit::BlockEditPlugin;
+use crate::utils::input::ActionsPlugin;
+use crate::utils::player::{MovementMode, PlayerPlugin};
+use crate::utils::time_of_day::{TimeOfDay, TimeOfDayPlugin};
 use crate::world::seed::WorldSeed;
+use crate::world::metadata::WorldMetadata;
+use crate::world::generator::{GeneratorKind, WorldGenerator};
+use crate::world::dungeon::DungeonLocator;
+use crate::world::preset::WorldPreset;
 
 
 fn main() {
@@ -33,11 +41,33 @@ fn main() {
                 close_when_requested: true,
             }),
     );
-    app.insert_resource(WorldSeed::default());
+    // Seed, generator and time from --seed / --generator / --time, then world.toml. --mode picks the movement mode.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut metadata = WorldMetadata::load();
+    let preset = WorldPreset::load();
+    let world_seed = WorldSeed::resolve(&args, &mut metadata);
+    let generator = WorldGenerator::resolve(&args, &mut metadata, world_seed.0, &preset);
+    let time_of_day = TimeOfDay::resolve(&args, &metadata);
+    metadata.time = time_of_day.clone();
+    metadata.save();
+    // Dungeons only exist in the noise terrain
+    if matches!(metadata.generator, GeneratorKind::Noise) {
+        app.insert_resource(DungeonLocator { seed: world_seed.0, config: preset.dungeons.clone() });
+    }
+    app.insert_resource(world_seed);
+    app.insert_resource(metadata);
+    app.insert_resource(preset);
+    app.insert_resource(generator);
+    app.insert_resource(time_of_day);
+    app.insert_resource(MovementMode::resolve(&args));
 
+    app.add_plugins(ActionsPlugin);
     app.add_plugins(ChunkPlugin);
     app.add_plugins(LightPlugin);
+    app.add_plugins(TimeOfDayPlugin);
     app.add_plugins(SimpleCameraPlugin);
+    app.add_plugins(PlayerPlugin);
+    app.add_plugins(BlockEditPlugin);
 
     app.run();
 }
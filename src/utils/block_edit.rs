@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+
+use crate::utils::input::{Action, Actions};
+use crate::utils::player::{Aabb, Player};
+use crate::world::chunk_manager::ChunkManager;
+use crate::world::raycast::{raycast, RaycastHit};
+use crate::world::voxel::Voxel;
+
+/// How far away blocks can be broken and placed
+const REACH: f32 = 6.0;
+
+pub(crate) struct BlockEditPlugin;
+
+impl Plugin for BlockEditPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SelectedBlock(Voxel::Planks));
+        app.init_resource::<TargetBlock>();
+        app.add_systems(Update, select_block);
+        // Target first, so edits and the outline use this frame's camera
+        app.add_systems(Update, (update_target, edit_blocks, draw_target_outline).chain());
+    }
+}
+
+/// Block placed by `PlaceBlock`
+#[derive(Resource)]
+pub struct SelectedBlock(pub Voxel);
+
+/// Block the camera is looking at, if it's in reach
+#[derive(Resource, Default)]
+pub struct TargetBlock(pub Option<RaycastHit>);
+
+/// `NextBlock` and `PreviousBlock` go through every block but air
+fn select_block(actions: Res<Actions>, mut selected: ResMut<SelectedBlock>) {
+    let step = if actions.just_pressed(Action::NextBlock) {
+        1
+    } else if actions.just_pressed(Action::PreviousBlock) {
+        Voxel::ALL.len() - 2
+    } else {
+        return;
+    };
+    let placeable = &Voxel::ALL[1..];
+    let current = placeable.iter().position(|&voxel| voxel == selected.0).unwrap_or(0);
+    selected.0 = placeable[(current + step) % placeable.len()];
+    info!("Selected block: {:?}", selected.0);
+}
+
+fn update_target(
+    chunk_manager: Res<ChunkManager>,
+    player: Query<(&Transform, &Player)>,
+    mut target: ResMut<TargetBlock>,
+) {
+    // Picking goes from the player's eyes, whatever the camera mode
+    target.0 = player
+        .single()
+        .ok()
+        .filter(|(_, player)| player.mode.can_interact())
+        .and_then(|(transform, _)| raycast(&chunk_manager, transform.translation, *transform.forward(), REACH));
+}
+
+/// Break the target block, or place the selected block against the face being looked at
+fn edit_blocks(
+    actions: Res<Actions>,
+    selected: Res<SelectedBlock>,
+    mut target: ResMut<TargetBlock>,
+    mut chunk_manager: ResMut<ChunkManager>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let Some(hit) = target.0 else {
+        return;
+    };
+
+    if actions.just_pressed(Action::BreakBlock) {
+        if chunk_manager.set_voxel(hit.block, Voxel::Air) {
+            target.0 = None;
+        }
+        return;
+    }
+
+    if !actions.just_pressed(Action::PlaceBlock) || hit.normal == IVec3::ZERO {
+        return;
+    }
+    let pos = hit.block + hit.normal;
+    // Only into air or liquid, and never into the player
+    if chunk_manager.voxel(pos).is_none_or(Voxel::is_solid) {
+        return;
+    }
+    let in_player = player.single().is_ok_and(|transform| Aabb::player(transform.translation).intersects_block(pos));
+    if selected.0.is_solid() && in_player {
+        return;
+    }
+    chunk_manager.set_voxel(pos, selected.0);
+}
+
+/// Wireframe around the target block
+fn draw_target_outline(target: Res<TargetBlock>, mut gizmos: Gizmos) {
+    if let Some(hit) = target.0 {
+        // Slightly larger than the block so the lines aren't hidden in its faces
+        let center = hit.block.as_vec3() + Vec3::splat(0.5);
+        gizmos.cuboid(Transform::from_translation(center).with_scale(Vec3::splat(1.01)), Color::BLACK);
+    }
+}
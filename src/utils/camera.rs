@@ -1,9 +1,15 @@
 use bevy::ecs::query::QuerySingleError;
-use bevy::input::mouse::MouseMotion;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
 use bevy::window::{CursorGrabMode, CursorOptions};
+use serde::{Deserialize, Serialize};
 
+use crate::utils::input::{Action, Actions};
+use crate::utils::config::{load_toml_or_default, save_toml};
+use crate::utils::player::{move_with_collision, MovementMode, Player, EYE_HEIGHT, PLAYER_HEIGHT, PLAYER_WIDTH};
+use crate::world::chunk_manager::ChunkManager;
 use crate::world::constants::CHUNK_SIZE;
+use crate::world::raycast::raycast;
 
 pub struct SimpleCameraPlugin;
 
@@ -11,9 +17,15 @@ impl Plugin for SimpleCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_camera);
         app.add_systems(Update, camera_movement);
+        app.add_systems(Update, scroll_speed);
+        app.add_systems(Last, save_settings);
         app.add_systems(Update, grab_mouse);
         app.add_systems(Update, mouse_look);
-        app.insert_resource(CameraSettings::default());
+        app.add_systems(Update, switch_camera_mode);
+        // After all movement, before transforms are propagated for rendering
+        app.add_systems(PostUpdate, place_camera.before(TransformSystems::Propagate));
+        app.insert_resource(CameraSettings::load());
+        app.init_resource::<CameraMode>();
 
     }
 }
@@ -21,13 +33,66 @@ impl Plugin for SimpleCameraPlugin {
 #[derive(Component)]
 pub(crate) struct FCamera;
 
-#[derive(Resource)]
-struct CameraSettings {
+/// Box drawn for the player when the camera isn't in its head
+#[derive(Component)]
+struct PlayerBody;
+
+/// Where the camera is relative to the player
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CameraMode {
+    #[default]
+    FirstPerson,
+    /// Behind and over the shoulder, pulled in when blocks are in the way
+    ThirdPerson,
+    /// Circles a fixed point, the mouse turns the orbit instead of the player
+    Orbit { center: Vec3, yaw: f32, pitch: f32 },
+}
+
+pub const CAMERA_SETTINGS_PATH: &str = "camera.toml";
+/// Seconds without changes before the settings are written
+const SAVE_DELAY: f32 = 1.0;
+
+/// Speeds of one movement mode, in blocks per second
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeSpeed {
     pub speed: f32,
+    /// Speed multiplier while sprint is held
+    pub sprint_multiplier: f32,
+}
+
+impl Default for ModeSpeed {
+    fn default() -> Self {
+        Self { speed: 20.0, sprint_multiplier: 3.0 }
+    }
+}
+
+/// Movement tuning, read from `camera.toml`
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub walk: ModeSpeed,
+    pub fly: ModeSpeed,
+    pub noclip: ModeSpeed,
+    pub spectator: ModeSpeed,
+    /// How quickly the camera reaches full speed and comes to a stop, per second
+    pub acceleration: f32,
+    pub damping: f32,
+    /// Speed change per scroll wheel notch, as a factor
+    pub scroll_factor: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Spring arm length and sideways shoulder offset of the third-person camera
+    pub third_person_distance: f32,
+    pub shoulder_offset: f32,
+    pub orbit_distance: f32,
+    /// Turn speed of the orbit camera when the mouse is still, radians per second
+    pub orbit_speed: f32,
 }
 
+/// Where the player looks. The camera follows it in first and third person.
 #[derive(Component)]
-struct CameraRotation {
+struct PlayerRotation {
     yaw: f32,
     pitch: f32,
 }
@@ -36,68 +101,210 @@ struct CameraRotation {
 
 impl Default for CameraSettings {
     fn default() -> Self {
-        Self { speed: 3.0 }
+        Self {
+            walk: ModeSpeed { speed: 4.3, sprint_multiplier: 1.3 },
+            fly: ModeSpeed { speed: 11.0, sprint_multiplier: 2.0 },
+            noclip: ModeSpeed::default(),
+            spectator: ModeSpeed::default(),
+            acceleration: 10.0,
+            damping: 8.0,
+            scroll_factor: 1.15,
+            min_speed: 1.0,
+            max_speed: 200.0,
+            third_person_distance: 4.0,
+            shoulder_offset: 0.6,
+            orbit_distance: 12.0,
+            orbit_speed: 0.2,
+        }
     }
 }
 
-fn setup_camera(mut commands: Commands) {
+impl CameraSettings {
+    pub fn load() -> Self {
+        let mut settings: Self = load_toml_or_default(CAMERA_SETTINGS_PATH);
+        // Scrolling clamps the speed between them, which needs them in order
+        if settings.min_speed > settings.max_speed {
+            warn!("{}: min_speed is above max_speed, swapping them", CAMERA_SETTINGS_PATH);
+            std::mem::swap(&mut settings.min_speed, &mut settings.max_speed);
+        }
+        settings
+    }
+
+    pub fn save(&self) {
+        save_toml(CAMERA_SETTINGS_PATH, self);
+    }
+
+    pub fn speed(&self, mode: MovementMode) -> &ModeSpeed {
+        match mode {
+            MovementMode::Walk => &self.walk,
+            MovementMode::Fly => &self.fly,
+            MovementMode::Noclip => &self.noclip,
+            MovementMode::Spectator => &self.spectator,
+        }
+    }
+
+    pub fn speed_mut(&mut self, mode: MovementMode) -> &mut ModeSpeed {
+        match mode {
+            MovementMode::Walk => &mut self.walk,
+            MovementMode::Fly => &mut self.fly,
+            MovementMode::Noclip => &mut self.noclip,
+            MovementMode::Spectator => &mut self.spectator,
+        }
+    }
+}
+
+/// The player entity sits at eye height, its body hangs below
+fn body_center() -> Vec3 {
+    Vec3::Y * (PLAYER_HEIGHT / 2.0 - EYE_HEIGHT)
+}
+
+fn setup_camera(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    start_mode: Option<Res<MovementMode>>,
+) {
+    commands.spawn((
+        Transform::from_xyz(0.0, 5.0, 10.0),
+        Visibility::default(),
+        PlayerRotation { yaw: 0.0, pitch: 0.0 },
+        Player { mode: start_mode.map_or_else(MovementMode::default, |mode| *mode), ..default() },
+        children![(
+            PlayerBody,
+            Mesh3d(meshes.add(Cuboid::new(PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_WIDTH))),
+            MeshMaterial3d(materials.add(Color::srgb(0.2, 0.4, 0.8))),
+            Transform::from_translation(body_center()),
+            Visibility::Hidden,
+        )],
+    ));
+
     commands.spawn((
         Camera3d::default(),
-        Transform::from_xyz(0.0, 5.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
+        Transform::from_xyz(0.0, 5.0, 10.0),
         GlobalTransform::default(),
         FCamera,
-        CameraRotation { yaw: 0.0, pitch: 0.0 },
     ));
 }
 
 
 fn camera_movement(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<&mut Transform, With<FCamera>>,
+    time: Res<Time>,
+    actions: Res<Actions>,
+    mut query: Query<(&mut Transform, &mut Player)>,
     settings: Res<CameraSettings>,
+    chunk_manager: Res<ChunkManager>,
 ) {
-    for mut transform in query.iter_mut() {
+    let dt = time.delta_secs();
+    for (mut transform, mut player) in query.iter_mut() {
+        // Walking is moved by the player controller
+        if player.mode == MovementMode::Walk {
+            continue;
+        }
         let mut direction = Vec3::ZERO;
         let forward = transform.rotation * Vec3::Z;
         let right = transform.rotation * Vec3::X;
 
-        if keyboard_input.pressed(KeyCode::KeyW) {
+        if actions.pressed(Action::MoveForward) {
             direction -= forward;
         }
-        if keyboard_input.pressed(KeyCode::KeyS) {
+        if actions.pressed(Action::MoveBack) {
             direction += forward;
         }
-        if keyboard_input.pressed(KeyCode::KeyA) {
+        if actions.pressed(Action::MoveLeft) {
             direction -= right;
         }
-        if keyboard_input.pressed(KeyCode::KeyD) {
+        if actions.pressed(Action::MoveRight) {
             direction += right;
         }
-        if keyboard_input.pressed(KeyCode::Space) {
+        if actions.pressed(Action::Jump) {
             direction.y += 1.0;
         }
-        if keyboard_input.pressed(KeyCode::ShiftLeft) {
+        if actions.pressed(Action::Descend) {
             direction.y -= 1.0;
         }
 
-        if direction != Vec3::ZERO {
-            transform.translation += direction.normalize() * settings.speed;
+        let mode_speed = settings.speed(player.mode);
+        let mut speed = mode_speed.speed;
+        if actions.pressed(Action::Sprint) {
+            speed *= mode_speed.sprint_multiplier;
         }
+        let target = direction.normalize_or_zero() * speed;
+
+        // Ease towards the wanted velocity, the same at any frame rate
+        let rate = if target == Vec3::ZERO { settings.damping } else { settings.acceleration };
+        let mut velocity = player.velocity.lerp(target, 1.0 - (-rate * dt).exp());
+        if player.mode == MovementMode::Fly {
+            let (eye, hit) = move_with_collision(&chunk_manager, transform.translation, velocity * dt);
+            transform.translation = eye;
+            // Stop pushing into walls
+            velocity = Vec3::select(hit, Vec3::ZERO, velocity);
+        } else {
+            transform.translation += velocity * dt;
+        }
+        player.velocity = velocity;
+    }
+}
+
+/// Scroll wheel changes the speed of the current movement mode
+fn scroll_speed(
+    mut wheel: MessageReader<MouseWheel>,
+    mut settings: ResMut<CameraSettings>,
+    players: Query<&Player>,
+) {
+    let mut notches = 0.0;
+    for ev in wheel.read() {
+        notches += match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / 100.0,
+        };
+    }
+    let Ok(player) = players.single() else {
+        return;
+    };
+    if notches == 0.0 {
+        return;
+    }
+    let factor = settings.scroll_factor.powf(notches);
+    let (min, max) = (settings.min_speed, settings.max_speed);
+    let mode_speed = settings.speed_mut(player.mode);
+    mode_speed.speed = (mode_speed.speed * factor).clamp(min, max);
+    info!("{:?} speed: {:.1} blocks/s", player.mode, mode_speed.speed);
+}
+
+/// Write `camera.toml` once the settings have stopped changing for a moment, and on exit
+fn save_settings(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    mut exit: MessageReader<AppExit>,
+    mut unsaved_for: Local<Option<f32>>,
+) {
+    if settings.is_changed() && !settings.is_added() {
+        *unsaved_for = Some(0.0);
     }
+    let exiting = exit.read().next().is_some();
+    let Some(idle) = unsaved_for.as_mut() else {
+        return;
+    };
+    *idle += time.delta_secs();
+    if *idle < SAVE_DELAY && !exiting {
+        return;
+    }
+    *unsaved_for = None;
+    settings.save();
 }
 
 
 fn grab_mouse(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<Actions>,
     mut cursor_opts: Query<&mut CursorOptions>,
 ) {
     if let Ok(mut opts) = cursor_opts.single_mut() {
-        if keyboard_input.just_pressed(KeyCode::KeyG) {
+        if actions.just_pressed(Action::GrabCursor) {
             opts.visible = false;
             opts.grab_mode = CursorGrabMode::Locked;
         }
 
-        if keyboard_input.just_pressed(KeyCode::KeyU) {
+        if actions.just_pressed(Action::ReleaseCursor) {
             opts.visible = true;
             opts.grab_mode = CursorGrabMode::None;
         }
@@ -106,19 +313,25 @@ fn grab_mouse(
 
 
 fn mouse_look(
-    mut motion_evr: EventReader<MouseMotion>,
-    mut query: Query<(&mut Transform, &mut CameraRotation), With<FCamera>>,
+    mut motion_evr: MessageReader<MouseMotion>,
+    mut query: Query<(&mut Transform, &mut PlayerRotation), Without<PlayerBody>>,
+    mut bodies: Query<&mut Transform, With<PlayerBody>>,
+    mut camera_mode: ResMut<CameraMode>,
 ) {
-    let (mut transform, mut rotation) = query.single_mut().expect("Camera not found");
-
-
     let sensitivity = 0.002; // adjust as needed
+    let delta: Vec2 = motion_evr.read().map(|ev| ev.delta).sum();
 
-    for ev in motion_evr.read() {
-        rotation.yaw -= ev.delta.x * sensitivity;
-        rotation.pitch -= ev.delta.y * sensitivity;
+    // The orbit camera turns on its own, the player stays as it is
+    if let CameraMode::Orbit { yaw, pitch, .. } = &mut *camera_mode {
+        *yaw -= delta.x * sensitivity;
+        *pitch = (*pitch - delta.y * sensitivity).clamp(-1.54, 1.54);
+        return;
     }
 
+    let (mut transform, mut rotation) = query.single_mut().expect("Player not found");
+    rotation.yaw -= delta.x * sensitivity;
+    rotation.pitch -= delta.y * sensitivity;
+
     // Clamp pitch so camera won't flip
     rotation.pitch = rotation.pitch.clamp(-1.54, 1.54);
 
@@ -126,6 +339,74 @@ fn mouse_look(
     transform.rotation =
         Quat::from_axis_angle(Vec3::Y, rotation.yaw) *
         Quat::from_axis_angle(Vec3::X, rotation.pitch);
+
+    // The body only turns sideways, undo the pitch around the eyes
+    let upright = Quat::from_axis_angle(Vec3::X, -rotation.pitch);
+    for mut body in &mut bodies {
+        *body = Transform::from_translation(upright * body_center()).with_rotation(upright);
+    }
+}
+
+/// `CycleCameraMode` goes first person, third person, orbit around where the player is
+fn switch_camera_mode(
+    actions: Res<Actions>,
+    mut camera_mode: ResMut<CameraMode>,
+    player: Query<(&Transform, &PlayerRotation), With<Player>>,
+    mut bodies: Query<&mut Visibility, With<PlayerBody>>,
+) {
+    if !actions.just_pressed(Action::CycleCameraMode) {
+        return;
+    }
+    let Ok((transform, rotation)) = player.single() else {
+        return;
+    };
+    *camera_mode = match *camera_mode {
+        CameraMode::FirstPerson => CameraMode::ThirdPerson,
+        // Start the orbit from behind the player
+        CameraMode::ThirdPerson => CameraMode::Orbit { center: transform.translation, yaw: rotation.yaw, pitch: -0.4 },
+        CameraMode::Orbit { .. } => CameraMode::FirstPerson,
+    };
+    for mut visibility in &mut bodies {
+        *visibility = if *camera_mode == CameraMode::FirstPerson { Visibility::Hidden } else { Visibility::Inherited };
+    }
+    info!("Camera mode: {:?}", *camera_mode);
+}
+
+/// Put the camera where the current mode wants it
+fn place_camera(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    chunk_manager: Res<ChunkManager>,
+    mut camera_mode: ResMut<CameraMode>,
+    player: Query<&Transform, (With<Player>, Without<FCamera>)>,
+    mut camera: Query<&mut Transform, With<FCamera>>,
+) {
+    let (Ok(player), Ok(mut camera)) = (player.single(), camera.single_mut()) else {
+        return;
+    };
+
+    match &mut *camera_mode {
+        CameraMode::FirstPerson => *camera = *player,
+        CameraMode::ThirdPerson => {
+            // Stop a bit in front of the first block along a ray
+            let clear = |origin: Vec3, direction: Vec3, distance: f32| {
+                raycast(&chunk_manager, origin, direction, distance).map_or(distance, |hit| (hit.distance - 0.2).max(0.0))
+            };
+            // Shoulder first, so a wall beside the player doesn't put the pivot inside it
+            let side = player.rotation * Vec3::X * settings.shoulder_offset.signum();
+            let pivot = player.translation + side * clear(player.translation, side, settings.shoulder_offset.abs());
+            // Then the spring arm back from there
+            let back = player.rotation * Vec3::Z;
+            let length = clear(pivot, back, settings.third_person_distance);
+            *camera = Transform::from_translation(pivot + back * length).with_rotation(player.rotation);
+        }
+        CameraMode::Orbit { center, yaw, pitch } => {
+            *yaw += settings.orbit_speed * time.delta_secs();
+            let rotation = Quat::from_axis_angle(Vec3::Y, *yaw) * Quat::from_axis_angle(Vec3::X, *pitch);
+            *camera = Transform::from_translation(*center + rotation * Vec3::Z * settings.orbit_distance)
+                .looking_at(*center, Vec3::Y);
+        }
+    }
 }
 
 
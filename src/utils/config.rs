@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+
+/// Load a TOML config file, falling back to `T::default()` if it's missing or invalid
+pub(crate) fn load_toml_or_default<T: DeserializeOwned + Default>(path: &str) -> T {
+    match fs::read_to_string(path) {
+        Ok(text) => match toml::from_str(&text) {
+            Ok(value) => {
+                info!("Loaded {}", path);
+                value
+            }
+            Err(err) => {
+                warn!("Failed to parse {}: {}, using defaults", path, err);
+                T::default()
+            }
+        },
+        Err(_) => T::default(), // no file = defaults
+    }
+}
+
+/// Write a config file back to disk, logging instead of failing
+pub(crate) fn save_toml<T: Serialize>(path: &str, value: &T) {
+    let text = match toml::to_string_pretty(value) {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("Failed to serialize {}: {}", path, err);
+            return;
+        }
+    };
+    if let Err(err) = fs::write(path, text) {
+        warn!("Failed to write {}: {}", path, err);
+    }
+}
+
+/// Value of `--name <value>` or `--name=<value>` on the command line
+pub(crate) fn cli_arg(args: &[String], name: &str) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next().cloned();
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
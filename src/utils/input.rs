@@ -0,0 +1,321 @@
+use bevy::input::InputSystems;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::utils::config::load_toml_or_default;
+
+pub const KEYBINDINGS_PATH: &str = "keybindings.toml";
+
+pub(crate) struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load());
+        app.init_resource::<Actions>();
+        // Right after Bevy reads the devices, so every Update system sees this frame's input
+        app.add_systems(PreUpdate, update_actions.after(InputSystems));
+    }
+}
+
+/// Something the player can do, bound to keys and buttons in `keybindings.toml`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    /// Jump when walking, up when flying
+    Jump,
+    /// Down when flying
+    Descend,
+    Sprint,
+    CycleMovementMode,
+    GrabCursor,
+    ReleaseCursor,
+    ToggleFullbright,
+    ToggleXRay,
+    ToggleFog,
+    RenderDistanceUp,
+    RenderDistanceDown,
+    FreezeTime,
+    TimeBack,
+    TimeForward,
+    LocateDungeon,
+    BreakBlock,
+    PlaceBlock,
+    NextBlock,
+    PreviousBlock,
+    CycleCameraMode,
+}
+
+impl Action {
+    pub const ALL: [Action; 24] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Descend,
+        Action::Sprint,
+        Action::CycleMovementMode,
+        Action::GrabCursor,
+        Action::ReleaseCursor,
+        Action::ToggleFullbright,
+        Action::ToggleXRay,
+        Action::ToggleFog,
+        Action::RenderDistanceUp,
+        Action::RenderDistanceDown,
+        Action::FreezeTime,
+        Action::TimeBack,
+        Action::TimeForward,
+        Action::LocateDungeon,
+        Action::BreakBlock,
+        Action::PlaceBlock,
+        Action::NextBlock,
+        Action::PreviousBlock,
+        Action::CycleCameraMode,
+    ];
+
+    /// Key of the action in `keybindings.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBack => "move_back",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Jump => "jump",
+            Action::Descend => "descend",
+            Action::Sprint => "sprint",
+            Action::CycleMovementMode => "cycle_movement_mode",
+            Action::GrabCursor => "grab_cursor",
+            Action::ReleaseCursor => "release_cursor",
+            Action::ToggleFullbright => "toggle_fullbright",
+            Action::ToggleXRay => "toggle_xray",
+            Action::ToggleFog => "toggle_fog",
+            Action::RenderDistanceUp => "render_distance_up",
+            Action::RenderDistanceDown => "render_distance_down",
+            Action::FreezeTime => "freeze_time",
+            Action::TimeBack => "time_back",
+            Action::TimeForward => "time_forward",
+            Action::LocateDungeon => "locate_dungeon",
+            Action::BreakBlock => "break_block",
+            Action::PlaceBlock => "place_block",
+            Action::NextBlock => "next_block",
+            Action::PreviousBlock => "previous_block",
+            Action::CycleCameraMode => "cycle_camera_mode",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    fn default_bindings(self) -> Vec<Binding> {
+        use Binding::{Gamepad, Key, Mouse};
+        match self {
+            Action::MoveForward => vec![Key(KeyCode::KeyW), Gamepad(GamepadButton::DPadUp)],
+            Action::MoveBack => vec![Key(KeyCode::KeyS), Gamepad(GamepadButton::DPadDown)],
+            Action::MoveLeft => vec![Key(KeyCode::KeyA), Gamepad(GamepadButton::DPadLeft)],
+            Action::MoveRight => vec![Key(KeyCode::KeyD), Gamepad(GamepadButton::DPadRight)],
+            Action::Jump => vec![Key(KeyCode::Space), Gamepad(GamepadButton::South)],
+            Action::Descend => vec![Key(KeyCode::ShiftLeft), Gamepad(GamepadButton::East)],
+            Action::Sprint => vec![Key(KeyCode::ControlLeft), Gamepad(GamepadButton::LeftThumb)],
+            Action::CycleMovementMode => vec![Key(KeyCode::KeyV), Gamepad(GamepadButton::North)],
+            Action::GrabCursor => vec![Key(KeyCode::KeyG)],
+            Action::ReleaseCursor => vec![Key(KeyCode::KeyU)],
+            Action::ToggleFullbright => vec![Key(KeyCode::KeyF)],
+            Action::ToggleXRay => vec![Key(KeyCode::KeyX)],
+            Action::ToggleFog => vec![Key(KeyCode::KeyO)],
+            Action::RenderDistanceUp => vec![Key(KeyCode::Equal)],
+            Action::RenderDistanceDown => vec![Key(KeyCode::Minus)],
+            Action::FreezeTime => vec![Key(KeyCode::KeyT)],
+            Action::TimeBack => vec![Key(KeyCode::BracketLeft)],
+            Action::TimeForward => vec![Key(KeyCode::BracketRight)],
+            Action::LocateDungeon => vec![Key(KeyCode::KeyL)],
+            Action::BreakBlock => vec![Mouse(MouseButton::Left), Gamepad(GamepadButton::RightTrigger2)],
+            Action::PlaceBlock => vec![Mouse(MouseButton::Right), Gamepad(GamepadButton::LeftTrigger2)],
+            Action::NextBlock => vec![Key(KeyCode::KeyE), Gamepad(GamepadButton::RightTrigger)],
+            Action::PreviousBlock => vec![Key(KeyCode::KeyQ), Gamepad(GamepadButton::LeftTrigger)],
+            Action::CycleCameraMode => vec![Key(KeyCode::F5), Gamepad(GamepadButton::Select)],
+        }
+    }
+}
+
+/// A physical input. Written as `KeyW`, `Mouse:Left` or `Gamepad:South` in the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+/// Keys that can be bound by name
+const KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Tab, KeyCode::Escape, KeyCode::Backspace,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::AltLeft, KeyCode::AltRight, KeyCode::CapsLock,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Backslash,
+    KeyCode::Semicolon, KeyCode::Quote, KeyCode::Backquote, KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+];
+
+const MOUSE_BUTTONS: &[MouseButton] =
+    &[MouseButton::Left, MouseButton::Right, MouseButton::Middle, MouseButton::Back, MouseButton::Forward];
+
+const GAMEPAD_BUTTONS: &[GamepadButton] = &[
+    GamepadButton::South, GamepadButton::East, GamepadButton::North, GamepadButton::West,
+    GamepadButton::LeftTrigger, GamepadButton::LeftTrigger2, GamepadButton::RightTrigger, GamepadButton::RightTrigger2,
+    GamepadButton::Select, GamepadButton::Start, GamepadButton::Mode,
+    GamepadButton::LeftThumb, GamepadButton::RightThumb,
+    GamepadButton::DPadUp, GamepadButton::DPadDown, GamepadButton::DPadLeft, GamepadButton::DPadRight,
+];
+
+/// Find a button by its Debug name
+fn find_named<T: Copy + std::fmt::Debug>(all: &[T], name: &str) -> Option<T> {
+    all.iter().copied().find(|item| format!("{:?}", item) == name)
+}
+
+impl Binding {
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(name) = text.strip_prefix("Mouse:") {
+            find_named(MOUSE_BUTTONS, name).map(Binding::Mouse)
+        } else if let Some(name) = text.strip_prefix("Gamepad:") {
+            find_named(GAMEPAD_BUTTONS, name).map(Binding::Gamepad)
+        } else {
+            find_named(KEYS, text).map(Binding::Key)
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse:{:?}", button),
+            Binding::Gamepad(button) => format!("Gamepad:{:?}", button),
+        }
+    }
+}
+
+/// `keybindings.toml` as written on disk: action name to binding names
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct KeybindingsFile {
+    bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// Bindings of every action. Actions missing from the file keep their defaults.
+#[derive(Resource, Clone, Debug)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self { bindings: Action::ALL.into_iter().map(|action| (action, action.default_bindings())).collect() }
+    }
+}
+
+impl InputMap {
+    /// Read `keybindings.toml` and warn about inputs used by several actions
+    pub fn load() -> Self {
+        let mut map = Self::default();
+        let file: KeybindingsFile = load_toml_or_default(KEYBINDINGS_PATH);
+        for (name, bindings) in &file.bindings {
+            let Some(action) = Action::from_name(name) else {
+                warn!("Unknown action '{}' in {}", name, KEYBINDINGS_PATH);
+                continue;
+            };
+            let parsed = bindings
+                .iter()
+                .filter_map(|text| {
+                    let binding = Binding::parse(text);
+                    if binding.is_none() {
+                        warn!("Unknown binding '{}' for {} in {}", text, name, KEYBINDINGS_PATH);
+                    }
+                    binding
+                })
+                .collect();
+            map.bindings.insert(action, parsed);
+        }
+        map.warn_conflicts();
+        map
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Inputs bound to more than one action, with those actions
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<Action>)> {
+        let mut users: HashMap<Binding, Vec<Action>> = HashMap::new();
+        for action in Action::ALL {
+            for &binding in self.bindings(action) {
+                users.entry(binding).or_default().push(action);
+            }
+        }
+        let mut conflicts: Vec<_> = users.into_iter().filter(|(_, actions)| actions.len() > 1).collect();
+        conflicts.sort_by_key(|(binding, _)| binding.name());
+        conflicts
+    }
+
+    fn warn_conflicts(&self) {
+        for (binding, actions) in self.conflicts() {
+            warn!("{} is bound to several actions: {:?}", binding.name(), actions);
+        }
+    }
+}
+
+/// Action state for this frame, read by systems instead of raw key codes
+#[derive(Resource, Default)]
+pub struct Actions {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl Actions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+fn update_actions(
+    map: Res<InputMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    mut actions: ResMut<Actions>,
+) {
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+    for action in Action::ALL {
+        for &binding in map.bindings(action) {
+            let (pressed, just_pressed) = match binding {
+                Binding::Key(key) => (keys.pressed(key), keys.just_pressed(key)),
+                Binding::Mouse(button) => (mouse.pressed(button), mouse.just_pressed(button)),
+                Binding::Gamepad(button) => (
+                    gamepads.iter().any(|gamepad| gamepad.pressed(button)),
+                    gamepads.iter().any(|gamepad| gamepad.just_pressed(button)),
+                ),
+            };
+            if pressed {
+                actions.pressed.insert(action);
+            }
+            if just_pressed {
+                actions.just_pressed.insert(action);
+            }
+        }
+    }
+}
@@ -1,4 +1,30 @@
+use bevy::camera::visibility::NoFrustumCulling;
+use bevy::light::{NotShadowCaster, NotShadowReceiver};
+use bevy::mesh::MeshVertexBufferLayoutRef;
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
 use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, RenderPipelineDescriptor, SpecializedMeshPipelineError};
+use bevy::shader::ShaderRef;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::input::{Action, Actions};
+use crate::utils::config::{load_toml_or_default, save_toml};
+use crate::utils::time_of_day::{smoothstep, TimeOfDay};
+use crate::world::chunk_manager::ChunkManager;
+use crate::world::chunk_materials::{ChunkMaterial, ChunkMaterials};
+use crate::world::constants::{CHUNK_SIZE, RENDER_DISTANCE};
+use crate::world::lighting::{brightness, LightChannel, LightStorage, MAX_LIGHT};
+use crate::world::voxel::Voxel;
+
+pub const RENDER_SETTINGS_PATH: &str = "render.toml";
+
+/// Skylight levels taken away at midnight
+const MAX_SKY_DARKNESS: f32 = 10.0;
+
+const SKY_SHADER_PATH: &str = "shaders/sky.wgsl";
+
+/// Far enough out that the terrain is always in front of the dome
+const SKY_RADIUS: f32 = 5000.0;
 
 pub(crate) struct LightPlugin;
 
@@ -13,9 +39,17 @@ impl Plugin for LightPlugin {
             });
 
 
-        app.add_systems(Startup, spawn_sun);
+        app.add_plugins(MaterialPlugin::<SkyMaterial>::default());
+        app.add_systems(Startup, (spawn_sun, spawn_sky));
+        app.add_systems(Update, update_sky);
+        app.add_systems(PostUpdate, follow_camera_with_sky.before(TransformSystems::Propagate));
+        app.add_systems(Update, update_sky_light);
         app.add_systems(Update, toggle_fullbright);
+        app.add_systems(Update, render_commands);
+        // After the sky, the fog takes over its color
+        app.add_systems(Update, update_fog.after(update_sky));
         app.insert_resource(Fullbright(false));
+        app.insert_resource(RenderSettings::load());
     }
 }
 
@@ -23,6 +57,72 @@ impl Plugin for LightPlugin {
 #[derive(Resource)]
 pub struct Fullbright(pub bool);
 
+/// View range and fog, read from `render.toml` and saved when changed in game
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Chunks meshed around the camera
+    pub render_distance: i32,
+    /// Seconds new chunks take to fade in, 0 shows them at once
+    pub fade_in: f32,
+    pub fog: FogSettings,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self { render_distance: RENDER_DISTANCE as i32, fade_in: 0.5, fog: FogSettings::default() }
+    }
+}
+
+impl RenderSettings {
+    pub fn load() -> Self {
+        load_toml_or_default(RENDER_SETTINGS_PATH)
+    }
+
+    pub fn save(&self) {
+        save_toml(RENDER_SETTINGS_PATH, self);
+    }
+
+    /// Render distance in blocks
+    pub fn view_distance(&self) -> f32 {
+        (self.render_distance * CHUNK_SIZE as i32) as f32
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FogSettings {
+    pub enabled: bool,
+    /// Fog starts and is full at these fractions of the render distance
+    pub start: f32,
+    pub end: f32,
+    /// How much the fog glows around the sun, 0 turns it off
+    pub sun_glow: f32,
+    /// Blocks seen through under water
+    pub water_distance: f32,
+    pub water_color: [f32; 3],
+    /// Skylight at the camera at or below which cave fog is used
+    pub cave_sky_light: u8,
+    pub cave_distance: f32,
+    pub cave_color: [f32; 3],
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            start: 0.6,
+            end: 0.95,
+            sun_glow: 0.5,
+            water_distance: 24.0,
+            water_color: [0.1, 0.25, 0.45],
+            cave_sky_light: 3,
+            cave_distance: 64.0,
+            cave_color: [0.03, 0.03, 0.05],
+        }
+    }
+}
+
 pub fn setup_lights(mut commands: Commands) {
     commands.spawn((
         PointLight {
@@ -36,9 +136,81 @@ pub fn setup_lights(mut commands: Commands) {
     ));
 }
 
+#[derive(Component)]
+struct Sun;
+
+#[derive(Component)]
+struct Moon;
+
+/// Dome around the camera the sky is drawn on
+#[derive(Component)]
+struct SkyDome;
+
+/// Sky gradient from the horizon up, brighter toward the sun. The horizon color
+/// is the clear color, so the fog fades the terrain right into it.
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+struct SkyMaterial {
+    #[uniform(0)]
+    horizon: LinearRgba,
+    #[uniform(1)]
+    zenith: LinearRgba,
+    /// Sunrise and sunset glow around the sun, alpha is its strength
+    #[uniform(2)]
+    glow: LinearRgba,
+    /// Direction to the sun, w is how bright its disk is
+    #[uniform(3)]
+    sun: Vec4,
+}
+
+impl Material for SkyMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SKY_SHADER_PATH.into()
+    }
+
+    // Seen from the inside
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+fn spawn_sky(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<SkyMaterial>>) {
+    commands.spawn((
+        SkyDome,
+        Mesh3d(meshes.add(Sphere::new(SKY_RADIUS).mesh().uv(32, 18))),
+        MeshMaterial3d(materials.add(SkyMaterial {
+            horizon: LinearRgba::BLACK,
+            zenith: LinearRgba::BLACK,
+            glow: LinearRgba::NONE,
+            sun: Vec4::Y,
+        })),
+        Transform::default(),
+        NotShadowCaster,
+        NotShadowReceiver,
+        // Always around the camera, never worth a frustum test
+        NoFrustumCulling,
+    ));
+}
+
+fn follow_camera_with_sky(
+    camera: Query<&Transform, (With<Camera3d>, Without<SkyDome>)>,
+    mut dome: Query<&mut Transform, With<SkyDome>>,
+) {
+    let (Ok(camera), Ok(mut dome)) = (camera.single(), dome.single_mut()) else {
+        return;
+    };
+    dome.translation = camera.translation;
+}
+
 fn spawn_sun(mut commands: Commands) {
-    // Directional light pointing downwards
+    // Directional light, turned by the time of day
     commands.spawn((
+        Sun,
         DirectionalLight {
             color: Color::WHITE,
             illuminance: 50000.0, // strong sunlight
@@ -48,10 +220,160 @@ fn spawn_sun(mut commands: Commands) {
         Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, -std::f32::consts::FRAC_PI_4, 0.0, 0.0)),
         GlobalTransform::default(),
     ));
+
+    // Faint bluish moonlight on the opposite side
+    commands.spawn((
+        Moon,
+        DirectionalLight {
+            color: Color::srgb(0.6, 0.7, 1.0),
+            illuminance: 0.0,
+            shadows_enabled: false,
+            ..Default::default()
+        },
+        Transform::default(),
+        GlobalTransform::default(),
+    ));
+}
+
+/// Move the sun and moon, and fade ambient light and the sky with the hour
+fn update_sky(
+    time_of_day: Res<TimeOfDay>,
+    mut sun: Query<(&mut DirectionalLight, &mut Transform), (With<Sun>, Without<Moon>)>,
+    mut moon: Query<(&mut DirectionalLight, &mut Transform), (With<Moon>, Without<Sun>)>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+    dome: Query<&MeshMaterial3d<SkyMaterial>, With<SkyDome>>,
+    mut sky_materials: ResMut<Assets<SkyMaterial>>,
+) {
+    let sun_dir = time_of_day.sun_direction();
+    let daylight = time_of_day.daylight();
+    // Strongest while the sun is close to the horizon
+    let sunset = (1.0 - sun_dir.y.abs() / 0.3).clamp(0.0, 1.0);
+
+    if let Ok((mut light, mut transform)) = sun.single_mut() {
+        light.illuminance = 50000.0 * smoothstep(-0.05, 0.25, sun_dir.y);
+        light.color = Color::srgb(1.0, 0.55, 0.3).mix(&Color::WHITE, smoothstep(0.0, 0.4, sun_dir.y));
+        *transform = Transform::default().looking_to(-sun_dir, Vec3::Y);
+    }
+
+    if let Ok((mut light, mut transform)) = moon.single_mut() {
+        light.illuminance = 400.0 * smoothstep(-0.05, 0.25, -sun_dir.y);
+        *transform = Transform::default().looking_to(sun_dir, Vec3::Y);
+    }
+
+    let night = Color::srgb(0.35, 0.4, 0.6);
+    ambient.color = night.mix(&Color::srgb(0.8, 0.8, 0.8), daylight); // light gray by day
+    ambient.brightness = 0.08 + 0.92 * daylight;
+
+    let sky = Color::srgb(0.01, 0.01, 0.05).mix(&Color::srgb(0.5, 0.7, 1.0), daylight);
+    let sunset_color = Color::srgb(0.9, 0.5, 0.3);
+    // Only the horizon takes the full sunset tint, the dome shades it toward the sun
+    clear_color.0 = sky.mix(&sunset_color, sunset * 0.3);
+
+    if let Some(material) = dome.single().ok().and_then(|handle| sky_materials.get_mut(handle)) {
+        let zenith = Color::srgb(0.0, 0.0, 0.02).mix(&Color::srgb(0.2, 0.4, 0.85), daylight);
+        material.horizon = clear_color.0.to_linear();
+        material.zenith = zenith.to_linear();
+        material.glow = sunset_color.to_linear().with_alpha(sunset);
+        material.sun = sun_dir.extend(smoothstep(-0.05, 0.05, sun_dir.y));
+    }
+}
+
+/// Darken voxel skylight at night, as if it was that many levels lower.
+/// Only the chunk materials change, the meshes keep their daylight.
+fn update_sky_light(
+    time_of_day: Res<TimeOfDay>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    let darkness = (1.0 - time_of_day.daylight()) * MAX_SKY_DARKNESS;
+    chunk_materials.set_sky_light(&mut materials, brightness(MAX_LIGHT as f32 - darkness));
+}
+
+/// Fog the camera is in, picked from the block and skylight at the camera
+fn fog_target(settings: &RenderSettings, sky: Color, chunk_manager: &ChunkManager, camera: Vec3) -> (Color, f32, f32) {
+    let fog = &settings.fog;
+    let pos = camera.floor().as_ivec3();
+    if chunk_manager.voxel(pos) == Some(Voxel::Water) {
+        let [r, g, b] = fog.water_color;
+        return (Color::srgb(r, g, b), 0.0, fog.water_distance);
+    }
+    // Unloaded counts as open sky
+    if chunk_manager.voxel(pos).is_some() && chunk_manager.light(pos, LightChannel::Sky) <= fog.cave_sky_light {
+        let [r, g, b] = fog.cave_color;
+        return (Color::srgb(r, g, b), fog.cave_distance * 0.25, fog.cave_distance);
+    }
+    let view = settings.view_distance();
+    (sky, view * fog.start, view * fog.end)
+}
+
+/// Keep the camera fog in line with the render distance, the sky and whatever the camera is in
+fn update_fog(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<RenderSettings>,
+    clear_color: Res<ClearColor>,
+    chunk_manager: Res<ChunkManager>,
+    mut cameras: Query<(Entity, &Transform, Option<&mut DistanceFog>), With<Camera3d>>,
+) {
+    for (entity, transform, fog) in &mut cameras {
+        if !settings.fog.enabled {
+            if fog.is_some() {
+                commands.entity(entity).remove::<DistanceFog>();
+            }
+            continue;
+        }
+
+        let (color, start, end) = fog_target(&settings, clear_color.0, &chunk_manager, transform.translation);
+        let Some(mut fog) = fog else {
+            commands.entity(entity).insert(DistanceFog {
+                color,
+                directional_light_color: Color::srgba(1.0, 0.85, 0.6, settings.fog.sun_glow),
+                directional_light_exponent: 30.0,
+                falloff: FogFalloff::Linear { start, end },
+            });
+            continue;
+        };
+
+        // Ease into cave and water fog instead of snapping
+        let t = (time.delta_secs() * 4.0).min(1.0);
+        let (old_start, old_end) = if let FogFalloff::Linear { start, end } = fog.falloff { (start, end) } else { (start, end) };
+        fog.color = fog.color.mix(&color, t);
+        fog.falloff = FogFalloff::Linear {
+            start: old_start + (start - old_start) * t,
+            end: old_end + (end - old_end) * t,
+        };
+        // The sun only glows through open air
+        let glow = if color == clear_color.0 { settings.fog.sun_glow } else { 0.0 };
+        fog.directional_light_color = Color::srgba(1.0, 0.85, 0.6, glow);
+    }
+}
+
+/// Render distance up and down (= and -) and fog toggle (O)
+fn render_commands(actions: Res<Actions>, mut settings: ResMut<RenderSettings>) {
+    if actions.just_pressed(Action::ToggleFog) {
+        settings.fog.enabled = !settings.fog.enabled;
+        info!("Fog: {}", settings.fog.enabled);
+    } else {
+        let step = if actions.just_pressed(Action::RenderDistanceUp) {
+            1
+        } else if actions.just_pressed(Action::RenderDistanceDown) {
+            -1
+        } else {
+            return;
+        };
+        let distance = (settings.render_distance + step).clamp(2, 48);
+        if distance == settings.render_distance {
+            return;
+        }
+        settings.render_distance = distance;
+        info!("Render distance: {} chunks", distance);
+    }
+    settings.save();
 }
 
-fn toggle_fullbright(input: Res<ButtonInput<KeyCode>>, mut fullbright: ResMut<Fullbright>) {
-    if input.just_pressed(KeyCode::KeyF) {
+fn toggle_fullbright(actions: Res<Actions>, mut fullbright: ResMut<Fullbright>) {
+    if actions.just_pressed(Action::ToggleFullbright) {
         fullbright.0 = !fullbright.0;
         info!("Fullbright: {}", fullbright.0);
     }
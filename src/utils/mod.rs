@@ -1,2 +1,7 @@
 pub(crate) mod camera;
-pub(crate) mod light;
\ No newline at end of file
+pub(crate) mod light;
+pub(crate) mod config;
+pub(crate) mod time_of_day;
+pub(crate) mod player;
+pub(crate) mod input;
+pub(crate) mod block_edit;
@@ -0,0 +1,333 @@
+use bevy::prelude::*;
+
+use crate::utils::input::{Action, Actions};
+use crate::utils::camera::CameraSettings;
+use crate::utils::config::cli_arg;
+use crate::world::chunk_manager::ChunkManager;
+use crate::world::constants::HEIGHT_BELOW;
+use crate::world::lighting::LightStorage;
+use crate::world::voxel::Voxel;
+
+/// Player box, the camera sits `EYE_HEIGHT` above its bottom center
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+pub const EYE_HEIGHT: f32 = 1.62;
+
+const GRAVITY: f32 = 28.0;
+const JUMP_SPEED: f32 = 9.0;
+/// Two jump presses within this many seconds toggle flying
+const DOUBLE_TAP_TIME: f32 = 0.3;
+const MAX_FALL_SPEED: f32 = 60.0;
+/// Highest ledge walked onto without jumping
+const STEP_HEIGHT: f32 = 1.0;
+/// Gap kept between the box and the blocks it touches
+const SKIN: f32 = 0.001;
+
+pub(crate) struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, switch_mode);
+        app.add_systems(Update, double_tap_jump);
+        app.add_systems(Update, walk);
+    }
+}
+
+/// How the player moves. As a resource it is the mode the player starts in.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Gravity and collision
+    Walk,
+    /// Free flight that still collides with blocks
+    Fly,
+    /// Free flight through everything
+    #[default]
+    Noclip,
+    /// Noclip that can't touch the world
+    Spectator,
+}
+
+impl MovementMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Walk => Self::Fly,
+            Self::Fly => Self::Noclip,
+            Self::Noclip => Self::Spectator,
+            Self::Spectator => Self::Walk,
+        }
+    }
+
+    /// Spectators only look around
+    pub fn can_interact(self) -> bool {
+        self != Self::Spectator
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "walk" => Some(Self::Walk),
+            "fly" => Some(Self::Fly),
+            "noclip" => Some(Self::Noclip),
+            "spectator" => Some(Self::Spectator),
+            _ => None,
+        }
+    }
+
+    /// Mode from `--mode <walk|fly|noclip|spectator>` on the command line
+    pub fn resolve(args: &[String]) -> Self {
+        let Some(value) = cli_arg(args, "--mode") else {
+            return Self::default();
+        };
+        Self::parse(&value).unwrap_or_else(|| {
+            warn!("Unknown --mode '{}', expected walk, fly, noclip or spectator", value);
+            Self::default()
+        })
+    }
+}
+
+/// Movement state of the camera entity
+#[derive(Component, Default)]
+pub struct Player {
+    pub mode: MovementMode,
+    pub velocity: Vec3,
+    pub on_ground: bool,
+}
+
+impl Player {
+    pub fn set_mode(&mut self, mode: MovementMode) {
+        self.mode = mode;
+        self.velocity = Vec3::ZERO;
+        self.on_ground = false;
+        info!("Movement mode: {:?}", mode);
+    }
+}
+
+/// Axis aligned box in world space
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Player box for an eye position
+    pub fn player(eye: Vec3) -> Self {
+        let feet = eye - Vec3::Y * EYE_HEIGHT;
+        let half = PLAYER_WIDTH / 2.0;
+        Self {
+            min: feet - Vec3::new(half, 0.0, half),
+            max: feet + Vec3::new(half, PLAYER_HEIGHT, half),
+        }
+    }
+
+    pub fn translated(self, offset: Vec3) -> Self {
+        Self { min: self.min + offset, max: self.max + offset }
+    }
+
+    /// Whether the box overlaps the block at `pos`
+    pub fn intersects_block(self, pos: IVec3) -> bool {
+        let min = pos.as_vec3();
+        let max = min + Vec3::ONE;
+        self.min.cmplt(max).all() && self.max.cmpgt(min).all()
+    }
+
+    /// Blocks the box touches
+    fn blocks(self) -> impl Iterator<Item = IVec3> {
+        let min = self.min.floor().as_ivec3();
+        let max = (self.max - Vec3::splat(SKIN)).floor().as_ivec3();
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).flat_map(move |z| (min.x..=max.x).map(move |x| IVec3::new(x, y, z))))
+    }
+}
+
+/// Block at `pos` for collision, `None` where the column isn't loaded.
+/// Above and below the world is air, so falling out of it never freezes the player.
+fn collision_block(chunk_manager: &ChunkManager, pos: IVec3) -> Option<Voxel> {
+    let bottom = -(HEIGHT_BELOW as i32);
+    let top = chunk_manager.max_y();
+    if pos.y < bottom || pos.y > top {
+        return chunk_manager.voxel(pos.with_y(pos.y.clamp(bottom, top))).map(|_| Voxel::Air);
+    }
+    chunk_manager.voxel(pos)
+}
+
+/// Unloaded blocks count as solid, so nobody walks off into chunks that don't exist yet
+fn blocked(chunk_manager: &ChunkManager, aabb: Aabb) -> bool {
+    aabb.blocks().any(|pos| collision_block(chunk_manager, pos).is_none_or(Voxel::is_solid))
+}
+
+/// Move the box along one axis until it hits something. Returns how far it got.
+fn move_axis(chunk_manager: &ChunkManager, aabb: &mut Aabb, axis: usize, delta: f32) -> f32 {
+    // Steps under half a block can't skip over a block
+    let steps = (delta.abs() / 0.45).ceil().max(1.0);
+    let step = delta / steps;
+    let mut moved = 0.0;
+    for _ in 0..steps as usize {
+        let mut offset = Vec3::ZERO;
+        offset[axis] = step;
+        let next = aabb.translated(offset);
+        if !blocked(chunk_manager, next) {
+            *aabb = next;
+            moved += step;
+            continue;
+        }
+        // Snap against the face of the block in the way
+        let edge = if step > 0.0 {
+            (aabb.max[axis] + step).floor() - aabb.max[axis] - SKIN
+        } else {
+            (aabb.min[axis] + step).ceil() - aabb.min[axis] + SKIN
+        };
+        if edge.abs() < step.abs() && edge * step > 0.0 {
+            offset[axis] = edge;
+            *aabb = aabb.translated(offset);
+            moved += edge;
+        }
+        break;
+    }
+    moved
+}
+
+/// Move an eye position by `delta`, one axis at a time, stopping at blocks.
+/// Axes that hit something are set in the returned mask.
+pub fn move_with_collision(chunk_manager: &ChunkManager, eye: Vec3, delta: Vec3) -> (Vec3, BVec3) {
+    let mut aabb = Aabb::player(eye);
+    let mut hit = BVec3::FALSE;
+    for axis in [1, 0, 2] {
+        if delta[axis] == 0.0 {
+            continue;
+        }
+        let moved = move_axis(chunk_manager, &mut aabb, axis, delta[axis]);
+        hit.set(axis, (moved - delta[axis]).abs() > SKIN);
+    }
+    (eye_of(aabb), hit)
+}
+
+fn eye_of(aabb: Aabb) -> Vec3 {
+    aabb.min + Vec3::new(PLAYER_WIDTH / 2.0, EYE_HEIGHT, PLAYER_WIDTH / 2.0)
+}
+
+/// `CycleMovementMode` (V by default) goes through the movement modes
+fn switch_mode(actions: Res<Actions>, mut players: Query<&mut Player>) {
+    if !actions.just_pressed(Action::CycleMovementMode) {
+        return;
+    }
+    for mut player in &mut players {
+        let mode = player.mode.next();
+        player.set_mode(mode);
+    }
+}
+
+/// Pressing jump twice quickly switches between walking and flying
+fn double_tap_jump(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    mut players: Query<&mut Player>,
+    mut last_press: Local<Option<f32>>,
+) {
+    if !actions.just_pressed(Action::Jump) {
+        return;
+    }
+    let now = time.elapsed_secs();
+    let double = last_press.is_some_and(|last| now - last < DOUBLE_TAP_TIME);
+    // A third press starts a new double tap
+    *last_press = if double { None } else { Some(now) };
+    if !double {
+        return;
+    }
+    for mut player in &mut players {
+        match player.mode {
+            MovementMode::Walk => player.set_mode(MovementMode::Fly),
+            MovementMode::Fly => player.set_mode(MovementMode::Walk),
+            _ => {}
+        }
+    }
+}
+
+/// Gravity, jumping and per-axis collision against the loaded blocks
+fn walk(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    chunk_manager: Res<ChunkManager>,
+    settings: Res<CameraSettings>,
+    mut players: Query<(&mut Transform, &mut Player)>,
+) {
+    let dt = time.delta_secs().min(0.1);
+    for (mut transform, mut player) in &mut players {
+        if player.mode != MovementMode::Walk {
+            continue;
+        }
+
+        // Frozen until the columns around and under the player have loaded
+        let mut aabb = Aabb::player(transform.translation);
+        let surroundings = Aabb { min: aabb.min - Vec3::Y, max: aabb.max };
+        if surroundings.blocks().any(|pos| collision_block(&chunk_manager, pos).is_none()) {
+            player.velocity = Vec3::ZERO;
+            continue;
+        }
+
+        // Walk on the ground plane the camera faces
+        let forward = (transform.rotation * Vec3::NEG_Z).with_y(0.0).normalize_or_zero();
+        let right = (transform.rotation * Vec3::X).with_y(0.0).normalize_or_zero();
+        let mut direction = Vec3::ZERO;
+        if actions.pressed(Action::MoveForward) {
+            direction += forward;
+        }
+        if actions.pressed(Action::MoveBack) {
+            direction -= forward;
+        }
+        if actions.pressed(Action::MoveLeft) {
+            direction -= right;
+        }
+        if actions.pressed(Action::MoveRight) {
+            direction += right;
+        }
+        let mut speed = settings.walk.speed;
+        if actions.pressed(Action::Sprint) {
+            speed *= settings.walk.sprint_multiplier;
+        }
+        let horizontal = direction.normalize_or_zero() * speed;
+        player.velocity.x = horizontal.x;
+        player.velocity.z = horizontal.z;
+
+        if player.on_ground && actions.pressed(Action::Jump) {
+            player.velocity.y = JUMP_SPEED;
+        }
+        player.velocity.y = (player.velocity.y - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+        // Vertical first, so landing is known before walking
+        let dy = player.velocity.y * dt;
+        let moved_y = move_axis(&chunk_manager, &mut aabb, 1, dy);
+        let hit_y = moved_y.abs() < dy.abs() - SKIN;
+        // Probe just below the feet, a frame's worth of gravity can be smaller than the skin
+        player.on_ground = dy <= 0.0 && blocked(&chunk_manager, aabb.translated(Vec3::NEG_Y * 2.0 * SKIN));
+        if hit_y || player.on_ground {
+            player.velocity.y = 0.0;
+        }
+
+        for axis in [0, 2] {
+            let delta = player.velocity[axis] * dt;
+            if delta == 0.0 {
+                continue;
+            }
+            let moved = move_axis(&chunk_manager, &mut aabb, axis, delta);
+            if (moved - delta).abs() <= SKIN {
+                continue;
+            }
+
+            // Blocked on the ground: try stepping up onto the ledge
+            if player.on_ground {
+                let mut offset = Vec3::ZERO;
+                offset[axis] = delta - moved;
+                let raised = aabb.translated(Vec3::Y * STEP_HEIGHT);
+                if !blocked(&chunk_manager, raised) && !blocked(&chunk_manager, raised.translated(offset)) {
+                    let mut stepped = raised.translated(offset);
+                    // Settle back down onto the step
+                    move_axis(&chunk_manager, &mut stepped, 1, -STEP_HEIGHT);
+                    aabb = stepped;
+                    continue;
+                }
+            }
+            player.velocity[axis] = 0.0;
+        }
+
+        transform.translation = eye_of(aabb);
+    }
+}
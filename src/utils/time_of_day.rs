@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::utils::input::{Action, Actions};
+use crate::utils::config::cli_arg;
+use crate::world::metadata::WorldMetadata;
+
+/// Seconds between saves of the current time into the world metadata
+const SAVE_INTERVAL: f32 = 30.0;
+
+pub(crate) struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, advance_time);
+        app.add_systems(Update, time_commands);
+        // Last, so the frame the app exits in still saves
+        app.add_systems(Last, save_time);
+    }
+}
+
+/// Clock of the world, saved with the world metadata
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeOfDay {
+    /// 0..24, the sun rises at 6 and is highest at 12
+    pub hour: f32,
+    /// Real seconds a full day takes
+    pub day_length: f32,
+    pub frozen: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self { hour: 8.0, day_length: 1200.0, frozen: false }
+    }
+}
+
+impl TimeOfDay {
+    /// Time from the world metadata, `--time <hour>` on the command line overrides it
+    pub fn resolve(args: &[String], metadata: &WorldMetadata) -> Self {
+        let mut time = metadata.time.clone();
+        if let Some(value) = cli_arg(args, "--time") {
+            match value.parse::<f32>() {
+                Ok(hour) => time.set_hour(hour),
+                Err(_) => warn!("Invalid --time '{}', expected an hour", value),
+            }
+        }
+        time
+    }
+
+    pub fn set_hour(&mut self, hour: f32) {
+        self.hour = hour.rem_euclid(24.0);
+    }
+
+    /// Unit vector pointing at the sun, it moves from east (+x) over the top to west
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hour - 6.0) / 24.0 * TAU;
+        Vec3::new(angle.cos(), angle.sin(), 0.25).normalize()
+    }
+
+    /// 0 at night, 1 during the day, smooth around sunrise and sunset
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.15, 0.25, self.sun_direction().y)
+    }
+}
+
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    if time_of_day.frozen || time_of_day.day_length <= 0.0 {
+        return;
+    }
+    let hour = time_of_day.hour + time.delta_secs() / time_of_day.day_length * 24.0;
+    time_of_day.set_hour(hour);
+}
+
+/// Freeze the clock (T) or step an hour back or forward ([ and ])
+fn time_commands(actions: Res<Actions>, mut time_of_day: ResMut<TimeOfDay>) {
+    if actions.just_pressed(Action::FreezeTime) {
+        time_of_day.frozen = !time_of_day.frozen;
+        info!("Time frozen: {}", time_of_day.frozen);
+    }
+
+    let step = if actions.just_pressed(Action::TimeForward) {
+        1.0
+    } else if actions.just_pressed(Action::TimeBack) {
+        -1.0
+    } else {
+        return;
+    };
+    let hour = time_of_day.hour.floor() + step;
+    time_of_day.set_hour(hour);
+    info!("Time set to {:02}:00", time_of_day.hour as u32);
+}
+
+/// Write the clock into `world.toml` every now and then and on exit
+fn save_time(
+    time: Res<Time>,
+    time_of_day: Res<TimeOfDay>,
+    mut metadata: ResMut<WorldMetadata>,
+    mut exit: MessageReader<AppExit>,
+    mut since_save: Local<f32>,
+) {
+    *since_save += time.delta_secs();
+    let exiting = exit.read().next().is_some();
+    if *since_save < SAVE_INTERVAL && !exiting {
+        return;
+    }
+    *since_save = 0.0;
+    metadata.time = time_of_day.clone();
+    metadata.save();
+}
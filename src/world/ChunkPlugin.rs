@@ -1,5 +1,7 @@
 use bevy::prelude::*;
-use crate::WorldSeed;use super::chunk_manager::{ChunkManager, update_chunks, poll_chunk_tasks,};
+use crate::WorldSeed;use super::chunk_manager::{ChunkManager, update_chunks, advance_chunks, poll_chunk_tasks, remesh_dirty_chunks,};
+use super::dungeon::locate_dungeon;
+use super::chunk_materials::{ChunkMaterial, ChunkMaterials, XRay, update_chunk_materials, toggle_xray, fade_in_chunks, update_xray_visibility};
 
 pub struct ChunkPlugin;
 
@@ -7,9 +9,24 @@ impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
         // Insert the ChunkManager resource
         app.insert_resource(ChunkManager::default())
+            // Materials shared by all chunks
+            .add_plugins(MaterialPlugin::<ChunkMaterial>::default())
+            .init_resource::<ChunkMaterials>()
+            .init_resource::<XRay>()
+            .add_systems(Update, toggle_xray)
+            // Render mode changes go to the shared materials after Update, same frame
+            .add_systems(PostUpdate, (update_chunk_materials, update_xray_visibility))
             // System to update which chunks are loaded/despawned
             .add_systems(Update, update_chunks)
+            // System to start the next generation stage of chunks that are ready for it
+            .add_systems(Update, advance_chunks)
             // System to poll finished async tasks and update chunk entities
-            .add_systems(Update, poll_chunk_tasks);
+            .add_systems(Update, poll_chunk_tasks)
+            // System to fade newly loaded slices in
+            .add_systems(Update, fade_in_chunks)
+            // System to remesh slices touched by block edits
+            .add_systems(Update, remesh_dirty_chunks)
+            // LocateDungeon logs the nearest dungeon
+            .add_systems(Update, locate_dungeon);
     }
 }
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW, TOTAL_HEIGHT};
+use crate::world::generator::ChunkGenerator;
+use crate::world::voxel::{Chunk, Voxel};
+
+/// One layer of a superflat world
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlatLayer {
+    pub block: Voxel,
+    pub thickness: u32,
+}
+
+pub fn default_flat_layers() -> Vec<FlatLayer> {
+    vec![
+        FlatLayer { block: Voxel::Solid, thickness: HEIGHT_BELOW as u32 + 3 },
+        FlatLayer { block: Voxel::Sand, thickness: 1 },
+    ]
+}
+
+/// Same column everywhere, built from a bottom-to-top layer list
+pub struct SuperflatGenerator {
+    column: [Voxel; TOTAL_HEIGHT],
+}
+
+impl SuperflatGenerator {
+    pub fn new(layers: Vec<FlatLayer>) -> Self {
+        let mut column = [Voxel::Air; TOTAL_HEIGHT];
+        let blocks = layers
+            .iter()
+            .flat_map(|layer| std::iter::repeat_n(layer.block, layer.thickness as usize));
+        for (slot, block) in column.iter_mut().zip(blocks) {
+            *slot = block;
+        }
+        Self { column }
+    }
+}
+
+impl ChunkGenerator for SuperflatGenerator {
+    fn generate(&self, _chunk_x: i32, _chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::empty();
+        for (y, block) in self.column.iter().enumerate() {
+            chunk.data[y] = [[*block as u8; CHUNK_SIZE]; CHUNK_SIZE];
+        }
+        chunk
+    }
+}
+
+/// Single floor at world y = 0, alternating between two blocks
+pub struct CheckerboardGenerator {
+    pub tile_size: i32,
+    pub blocks: [Voxel; 2],
+}
+
+impl ChunkGenerator for CheckerboardGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::empty();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let world_x = chunk_x * CHUNK_SIZE as i32 + x as i32;
+                let world_z = chunk_z * CHUNK_SIZE as i32 + z as i32;
+                let tile = world_x.div_euclid(self.tile_size) + world_z.div_euclid(self.tile_size);
+                chunk.data[HEIGHT_BELOW][z][x] = self.blocks[tile.rem_euclid(2) as usize] as u8;
+            }
+        }
+        chunk
+    }
+}
+
+/// Empty world, mostly for renderer and loading benchmarks
+pub struct VoidGenerator;
+
+impl ChunkGenerator for VoidGenerator {
+    fn generate(&self, _chunk_x: i32, _chunk_z: i32) -> Chunk {
+        Chunk::empty()
+    }
+}
+
+/// Every block type (except air) in rows starting at the world origin,
+/// two blocks apart so all faces stay visible
+pub struct DebugGridGenerator;
+
+impl DebugGridGenerator {
+    const WORLD_Y: i32 = 8;
+    const SPACING: i32 = 2;
+
+    fn block_at(world_x: i32, world_z: i32) -> Option<Voxel> {
+        if world_x < 0 || world_z < 0 || world_x % Self::SPACING != 0 || world_z % Self::SPACING != 0 {
+            return None;
+        }
+        let blocks = &Voxel::ALL[1..];
+        let per_row = (blocks.len() as f32).sqrt().ceil() as i32;
+        let (col, row) = (world_x / Self::SPACING, world_z / Self::SPACING);
+        if col >= per_row {
+            return None;
+        }
+        blocks.get((row * per_row + col) as usize).copied()
+    }
+}
+
+impl ChunkGenerator for DebugGridGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::empty();
+        let y = (Self::WORLD_Y + HEIGHT_BELOW as i32) as usize;
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let world_x = chunk_x * CHUNK_SIZE as i32 + x as i32;
+                let world_z = chunk_z * CHUNK_SIZE as i32 + z as i32;
+                if let Some(block) = Self::block_at(world_x, world_z) {
+                    chunk.data[y][z][x] = block as u8;
+                }
+            }
+        }
+        chunk
+    }
+}
@@ -1,107 +1,251 @@
 use bevy::prelude::*;
 use bevy::tasks::{AsyncComputeTaskPool, Task};
 use futures_lite::future;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use crate::world::voxel::{Chunk, Voxel};
-use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW, RENDER_DISTANCE, TOTAL_HEIGHT, VERTICAL_CHUNK_HEIGHT};
-use crate::utils::light::Fullbright;
-use crate::world::mesher::{build_chunk_mesh, build_vertical_chunk_mesh};
-use crate::WorldSeed;
-
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW, TOTAL_HEIGHT, VERTICAL_CHUNK_HEIGHT};
+use crate::world::mesher::{build_chunk_mesh, build_vertical_chunk_mesh, ChunkMeshes};
+use crate::world::generator::WorldGenerator;
+use crate::world::chunk_materials::{faded_material, ChunkFade, ChunkMaterial, ChunkMaterials, XRayOnly};
+use crate::world::pipeline::{run_stage, ChunkNeighborhood, ChunkStatus};
+use crate::utils::light::RenderSettings;
+use crate::world::lighting::{pack_rgb, unpack_rgb, update_light, LightChannel, LightStorage, MAX_LIGHT};
+
+/// How far past the view each stage is generated. A stage only runs once the
+/// neighbours reached the stage before it, so meshed columns need lit neighbours,
+/// lit columns need featured neighbours and so on. Steps are > sqrt(2) apart.
+const STAGE_RINGS: [(f32, ChunkStatus); 4] = [
+    (0.0, ChunkStatus::Mesh),
+    (1.5, ChunkStatus::Light),
+    (3.0, ChunkStatus::Features),
+    (4.5, ChunkStatus::Carvers),
+];
+
+/// Stage tasks running at once, nearest columns go first
+const MAX_STAGE_TASKS: usize = 32;
+
+/// Slice remeshes running at once, nearest slices go first
+const MAX_REMESH_TASKS: usize = 8;
 
 #[derive(Resource, Default)]
 pub struct ChunkManager {
-    pub loaded_chunks: HashMap<(i32, i32, i32), Entity>, // now includes vertical layer
-    pub load_queue: Vec<ChunkLoadTask>,
+    pub loaded_chunks: HashMap<(i32, i32, i32), Entity>, // one entity per vertical layer
+    /// Voxel data and generation status of every chunk column
+    pub columns: HashMap<(i32, i32), ChunkColumn>,
+    /// Column keys nearest first, rebuilt when the player enters another chunk
+    order: Vec<(i32, i32)>,
+    center: Option<(i32, i32)>,
+    /// Slices (x, z, layer) whose blocks or light changed since they were meshed
+    pub dirty: HashSet<(i32, i32, i32)>,
+    /// Running slice remeshes, the old mesh stays up until they finish
+    remesh_tasks: HashMap<(i32, i32, i32), Task<ChunkMeshes>>,
 }
 
+pub struct ChunkColumn {
+    /// Last finished stage
+    pub status: ChunkStatus,
+    /// Stage this column should reach, from its distance to the player
+    pub target: ChunkStatus,
+    pub chunk: Arc<Chunk>,
+    task: Option<Task<StageResult>>,
+}
 
-pub struct ChunkLoadTask {
-    pub task: Task<((i32, i32, i32), Chunk, Mesh)>,
+enum StageResult {
+    Generated(ChunkStatus, Chunk),
+    Meshed(Vec<ChunkMeshes>),
 }
 
+impl ChunkColumn {
+    fn new() -> Self {
+        Self {
+            status: ChunkStatus::Empty,
+            target: ChunkStatus::Empty,
+            chunk: Arc::new(Chunk::empty()),
+            task: None,
+        }
+    }
+}
+
+/// Child of a chunk slice entity holding its water mesh
 #[derive(Component)]
-pub struct ChunkComponent(pub Chunk);
+pub struct ChunkWater;
 
 impl Chunk {
     pub fn empty() -> Self {
         Self {
             data: [[[Voxel::Air as u8; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT],
+            sky_light: [[[0; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT],
+            block_light: None,
         }
     }
 }
 
-/*
+impl ChunkManager {
+    pub fn status(&self, chunk_x: i32, chunk_z: i32) -> ChunkStatus {
+        self.columns.get(&(chunk_x, chunk_z)).map_or(ChunkStatus::Empty, |column| column.status)
+    }
 
-/// Spawn a placeholder chunk and enqueue async mesh generation
-fn spawn_chunk(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    chunk_pos: (i32, i32),
-    seed: u64,
-    fullbright: bool,
-    xray: bool,
-    chunk_manager: &mut ChunkManager,
-) {
-    let chunk_world_x = chunk_pos.0 as f32 * CHUNK_SIZE as f32;
-    let chunk_world_z = chunk_pos.1 as f32 * CHUNK_SIZE as f32;
+    /// Whether all 8 neighbours are far enough along for `stage` to run
+    fn neighbors_ready(&self, chunk_x: i32, chunk_z: i32, stage: ChunkStatus) -> bool {
+        let Some(required) = stage.neighbor_requirement() else {
+            return true;
+        };
+        (-1..=1).all(|dx| {
+            (-1..=1).all(|dz| (dx == 0 && dz == 0) || self.status(chunk_x + dx, chunk_z + dz) >= required)
+        })
+    }
 
-    // place chunk at correct Y = world base
-    let chunk_world_y = -(HEIGHT_BELOW as f32); // -32
+    fn neighborhood(&self, chunk_x: i32, chunk_z: i32) -> ChunkNeighborhood {
+        let chunks = std::array::from_fn(|i| {
+            let (dx, dz) = (i as i32 % 3 - 1, i as i32 / 3 - 1);
+            self.columns.get(&(chunk_x + dx, chunk_z + dz)).map(|column| column.chunk.clone())
+        });
+        ChunkNeighborhood::new(chunk_x, chunk_z, chunks)
+    }
 
-    let mut mesh = Mesh::from(Cuboid::new(
-        CHUNK_SIZE as f32,
-        TOTAL_HEIGHT as f32,
-        CHUNK_SIZE as f32,
-    ));
+    fn despawn_layers(&mut self, commands: &mut Commands, chunk_x: i32, chunk_z: i32) {
+        for layer in 0..num_vertical_chunks() {
+            if let Some(entity) = self.loaded_chunks.remove(&(chunk_x, chunk_z, layer)) {
+                commands.entity(entity).despawn();
+            }
+            // Dropping the task cancels it
+            self.remesh_tasks.remove(&(chunk_x, chunk_z, layer));
+        }
+    }
+}
 
-    // shift AABB upward so bottom matches -HEIGHT_BELOW (-32)
-    let shift_y = (TOTAL_HEIGHT as f32 / 2.0) - (HEIGHT_BELOW as f32);
-    mesh.translate_by(Vec3::new(0.0, shift_y, 0.0));
+#[cfg(test)]
+impl ChunkManager {
+    /// All air, lit columns up to `radius` chunks from the origin
+    pub(crate) fn empty_world(radius: i32) -> Self {
+        let mut manager = Self::default();
+        for chunk_x in -radius..=radius {
+            for chunk_z in -radius..=radius {
+                let mut column = ChunkColumn::new();
+                column.status = ChunkStatus::Mesh;
+                column.target = ChunkStatus::Mesh;
+                manager.columns.insert((chunk_x, chunk_z), column);
+            }
+        }
+        manager
+    }
+}
 
-    let placeholder_mesh = meshes.add(mesh);
+/// Column key and chunk-local (x, y, z) index of a world position
+fn locate(pos: IVec3) -> Option<((i32, i32), usize, usize, usize)> {
+    let size = CHUNK_SIZE as i32;
+    let y = pos.y + HEIGHT_BELOW as i32;
+    if y < 0 || y >= TOTAL_HEIGHT as i32 {
+        return None;
+    }
+    let key = (pos.x.div_euclid(size), pos.z.div_euclid(size));
+    Some((key, pos.x.rem_euclid(size) as usize, y as usize, pos.z.rem_euclid(size) as usize))
+}
 
-    let placeholder_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.2, 0.2, 0.2),
-        unlit: fullbright,
-        alpha_mode: if xray { AlphaMode::Blend } else { AlphaMode::Opaque },
-        ..default()
-    });
+impl ChunkManager {
+    /// Lit column holding a world position. Columns still being generated don't count.
+    fn finished_column(&self, pos: IVec3) -> Option<(&ChunkColumn, usize, usize, usize)> {
+        let (key, x, y, z) = locate(pos)?;
+        let column = self.columns.get(&key).filter(|column| column.status >= ChunkStatus::Light)?;
+        Some((column, x, y, z))
+    }
 
-    let entity = commands
-        .spawn((
-            ChunkComponent(Chunk::empty()),
-            Mesh3d(placeholder_mesh),
-            MeshMaterial3d(placeholder_material),
-            Transform::from_xyz(chunk_world_x, chunk_world_y, chunk_world_z),
-            GlobalTransform::default(),
-            Visibility::default(),
-        ))
-        .id();
+    /// Block at a world position, `None` if it isn't loaded
+    pub fn voxel(&self, pos: IVec3) -> Option<Voxel> {
+        self.finished_column(pos).map(|(column, x, y, z)| Voxel::from_u8(column.chunk.data[y][z][x]))
+    }
 
-    chunk_manager.loaded_chunks.insert(chunk_pos, entity);
+    /// Replace a block, relight around it and queue the touched slices for remeshing.
+    /// Returns false if the position isn't loaded.
+    pub fn set_voxel(&mut self, pos: IVec3, voxel: Voxel) -> bool {
+        if self.finished_column(pos).is_none() {
+            return false;
+        }
+        let Some((key, x, y, z)) = locate(pos) else {
+            return false;
+        };
+        if let Some(column) = self.columns.get_mut(&key) {
+            Arc::make_mut(&mut column.chunk).data[y][z][x] = voxel as u8;
+        }
+        self.mark_dirty(pos);
+        update_light(self, pos);
+        true
+    }
 
-    // Async chunk generation
-    let thread_pool = AsyncComputeTaskPool::get();
-    let task = thread_pool.spawn(async move {
-        let chunk = Chunk::new(chunk_pos.0, chunk_pos.1, seed);
-        let mesh = build_chunk_mesh(&chunk);
-        (chunk_pos, chunk, mesh)
-    });
-    chunk_manager.load_queue.push(ChunkLoadTask { task });
-}*/
+    /// Queue the slice of `pos` and any slice sharing a face with it
+    fn mark_dirty(&mut self, pos: IVec3) {
+        for offset in [IVec3::ZERO, IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z] {
+            if let Some(((chunk_x, chunk_z), _, y, _)) = locate(pos + offset) {
+                self.dirty.insert((chunk_x, chunk_z, (y / VERTICAL_CHUNK_HEIGHT) as i32));
+            }
+        }
+    }
+}
+
+impl LightStorage for ChunkManager {
+    fn voxel(&self, pos: IVec3) -> Option<Voxel> {
+        ChunkManager::voxel(self, pos)
+    }
+
+    fn light(&self, pos: IVec3, channel: LightChannel) -> u8 {
+        if pos.y > self.max_y() {
+            return if channel == LightChannel::Sky { MAX_LIGHT } else { 0 };
+        }
+        let Some((column, x, y, z)) = self.finished_column(pos) else {
+            return 0;
+        };
+        match channel {
+            LightChannel::Sky => column.chunk.sky_light[y][z][x],
+            LightChannel::Block(c) => column.chunk.block_light.as_ref().map_or(0, |grid| unpack_rgb(grid[y][z][x])[c]),
+        }
+    }
+
+    fn set_light(&mut self, pos: IVec3, channel: LightChannel, level: u8) {
+        if self.finished_column(pos).is_none() {
+            return;
+        }
+        let Some((key, x, y, z)) = locate(pos) else {
+            return;
+        };
+        if let Some(column) = self.columns.get_mut(&key) {
+            let chunk = Arc::make_mut(&mut column.chunk);
+            match channel {
+                LightChannel::Sky => chunk.sky_light[y][z][x] = level,
+                LightChannel::Block(c) => {
+                    if chunk.block_light.is_none() && level == 0 {
+                        return;
+                    }
+                    let grid = chunk
+                        .block_light
+                        .get_or_insert_with(|| Box::new([[[0; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT]));
+                    let mut rgb = unpack_rgb(grid[y][z][x]);
+                    rgb[c] = level;
+                    grid[y][z][x] = pack_rgb(rgb);
+                }
+            }
+        }
+        self.mark_dirty(pos);
+    }
 
-/// Update chunks around the camera
+    fn max_y(&self) -> i32 {
+        TOTAL_HEIGHT as i32 - HEIGHT_BELOW as i32 - 1
+    }
+}
+
+fn num_vertical_chunks() -> i32 {
+    TOTAL_HEIGHT.div_ceil(VERTICAL_CHUNK_HEIGHT) as i32
+}
+
+
+/// Set the target stage of every column around the camera, drop the ones out of range
 pub(crate) fn update_chunks(
     mut commands: Commands,
     mut chunk_manager: ResMut<ChunkManager>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    generator: Res<WorldGenerator>,
+    settings: Res<RenderSettings>,
     camera: Query<&Transform, With<Camera3d>>,
-    world_seed: Res<WorldSeed>,
-    fullbright: Res<Fullbright>,
 ) {
     let camera_transform = match camera.single() {
         Ok(t) => t,
@@ -112,186 +256,312 @@ pub(crate) fn update_chunks(
     let player_chunk_x = (camera_pos.x / CHUNK_SIZE as f32).floor() as i32;
     let player_chunk_z = (camera_pos.z / CHUNK_SIZE as f32).floor() as i32;
 
-    const VIEW_DISTANCE: i32 = RENDER_DISTANCE as i32;
-    const VERTICAL_BUFFER: f32 = 20.0; // in blocks
-    let visible_distance = VIEW_DISTANCE as f32 * CHUNK_SIZE as f32;
+    // Targets only change when the player crosses into another chunk or the render distance changes
+    let center = (player_chunk_x, player_chunk_z);
+    if chunk_manager.center == Some(center) && !settings.is_changed() {
+        return;
+    }
+    chunk_manager.center = Some(center);
+
+    let view = settings.render_distance as f32;
+    let reach = (view + STAGE_RINGS[STAGE_RINGS.len() - 1].0).ceil() as i32;
+    // Stages also read the ring of chunks just outside
+    generator.0.set_reach((reach + 1) * CHUNK_SIZE as i32);
+    let mut targets = HashMap::new();
+    for dx in -reach..=reach {
+        for dz in -reach..=reach {
+            let beyond = ((dx * dx + dz * dz) as f32).sqrt() - view;
+            if let Some(&(_, stage)) = STAGE_RINGS.iter().find(|(ring, _)| beyond <= *ring) {
+                targets.insert((center.0 + dx, center.1 + dz), stage);
+            }
+        }
+    }
+
+    let manager = &mut *chunk_manager;
 
     // -----------------------------
-    // Despawn far chunks
+    // Drop far columns, unmesh the ones that left the view
     // -----------------------------
     let mut to_despawn = Vec::new();
-    for (&(cx, cz, layer), &entity) in chunk_manager.loaded_chunks.iter() {
-        let chunk_world_x = cx as f32 * CHUNK_SIZE as f32;
-        let chunk_world_z = cz as f32 * CHUNK_SIZE as f32;
-        let chunk_world_y = -(HEIGHT_BELOW as f32) + layer as f32 * VERTICAL_CHUNK_HEIGHT as f32;
-
-        let horizontal_dist_sq = (chunk_world_x - camera_pos.x).powi(2)
-            + (chunk_world_z - camera_pos.z).powi(2);
-        let vertical_dist = (chunk_world_y - camera_pos.y).abs();
-
-        if horizontal_dist_sq > visible_distance.powi(2)
-            || vertical_dist > VERTICAL_BUFFER * CHUNK_SIZE as f32
-        {
-            to_despawn.push((cx, cz, layer));
+    manager.columns.retain(|key, column| match targets.get(key) {
+        None => {
+            to_despawn.push(*key);
+            false
         }
-    }
-
-    for key in to_despawn {
-        if let Some(entity) = chunk_manager.loaded_chunks.remove(&key) {
-            commands.entity(entity).despawn();
+        Some(&target) => {
+            column.target = target;
+            // Meshes can outlive a drop back to the light stage, so check the target
+            if target < ChunkStatus::Mesh {
+                column.status = column.status.min(ChunkStatus::Light);
+                to_despawn.push(*key);
+            }
+            true
         }
+    });
+    for (x, z) in to_despawn {
+        manager.despawn_layers(&mut commands, x, z);
     }
 
     // -----------------------------
-    // Spawn new chunks
+    // Queue new columns, nearest first
     // -----------------------------
-    let num_vertical_chunks = (TOTAL_HEIGHT as f32 / VERTICAL_CHUNK_HEIGHT as f32).ceil() as i32;
-
-    for dx in -VIEW_DISTANCE..=VIEW_DISTANCE {
-        for dz in -VIEW_DISTANCE..=VIEW_DISTANCE {
-            let base_chunk_x = player_chunk_x + dx;
-            let base_chunk_z = player_chunk_z + dz;
-
-            // Only spawn vertical layers that are missing
-            for layer in 0..num_vertical_chunks {
-                let key = (base_chunk_x, base_chunk_z, layer);
-                if chunk_manager.loaded_chunks.contains_key(&key) {
-                    continue;
-                }
+    for (&key, &target) in &targets {
+        manager.columns.entry(key).or_insert_with(ChunkColumn::new).target = target;
+    }
+    manager.order = targets.into_keys().collect();
+    manager.order.sort_by_key(|(x, z)| (x - center.0).pow(2) + (z - center.1).pow(2));
+}
 
-                let chunk_world_x = base_chunk_x as f32 * CHUNK_SIZE as f32;
-                let chunk_world_z = base_chunk_z as f32 * CHUNK_SIZE as f32;
-                let horizontal_dist_sq = (chunk_world_x - camera_pos.x).powi(2)
-                    + (chunk_world_z - camera_pos.z).powi(2);
+/// Start the next stage on columns whose neighbours are ready for it
+pub(crate) fn advance_chunks(mut chunk_manager: ResMut<ChunkManager>, generator: Res<WorldGenerator>) {
+    let manager = &mut *chunk_manager;
+    let thread_pool = AsyncComputeTaskPool::get();
+    let mut running = manager.columns.values().filter(|column| column.task.is_some()).count();
 
-                if horizontal_dist_sq > visible_distance.powi(2) {
-                    continue;
-                }
+    for i in 0..manager.order.len() {
+        if running >= MAX_STAGE_TASKS {
+            break;
+        }
+        let key = manager.order[i];
+        let Some(column) = manager.columns.get(&key) else {
+            continue;
+        };
+        if column.task.is_some() || column.status >= column.target {
+            continue;
+        }
+        let Some(stage) = column.status.next() else {
+            continue;
+        };
+        if !manager.neighbors_ready(key.0, key.1, stage) {
+            continue;
+        }
 
-                // Spawn vertical chunk
-                if !chunk_manager.loaded_chunks.contains_key(&key) {
-                    spawn_vertical_chunk_layer(
-                        &mut commands,
-                        &mut meshes,
-                        &mut materials,
-                        (base_chunk_x, base_chunk_z),
-                        layer,
-                        world_seed.0,
-                        fullbright.0,
-                        false,
-                        &mut chunk_manager,
-                    );
-                }
+        let area = manager.neighborhood(key.0, key.1);
+        let task = if stage == ChunkStatus::Mesh {
+            thread_pool.spawn(async move {
+                let layers = (0..num_vertical_chunks())
+                    .map(|layer| build_vertical_chunk_mesh(&area, layer as usize * VERTICAL_CHUNK_HEIGHT))
+                    .collect();
+                StageResult::Meshed(layers)
+            })
+        } else {
+            let generator = generator.0.clone();
+            thread_pool.spawn(async move { StageResult::Generated(stage, run_stage(generator.as_ref(), stage, &area)) })
+        };
 
-                break; // spawn per (x,z) only once; all vertical layers handled inside
-            }
+        if let Some(column) = manager.columns.get_mut(&key) {
+            column.task = Some(task);
+            running += 1;
         }
     }
 }
 
-
-
-
-
-/// Poll finished async chunk tasks and update placeholder entities
+/// Poll finished stage tasks, store new chunk data and spawn finished meshes
 pub(crate) fn poll_chunk_tasks(
     mut commands: Commands,
     mut chunk_manager: ResMut<ChunkManager>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    fullbright: Res<Fullbright>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    chunk_materials: Res<ChunkMaterials>,
+    settings: Res<RenderSettings>,
 ) {
+    let manager = &mut *chunk_manager;
     let mut finished = Vec::new();
 
     // Poll async tasks
-    chunk_manager.load_queue.retain_mut(|load_task| {
-        if let Some(result) = future::block_on(future::poll_once(&mut load_task.task)) {
-            finished.push(result);
-            false
-        } else {
-            true
+    for (&key, column) in manager.columns.iter_mut() {
+        let Some(task) = &mut column.task else {
+            continue;
+        };
+        let Some(result) = future::block_on(future::poll_once(task)) else {
+            continue;
+        };
+        column.task = None;
+
+        match result {
+            StageResult::Generated(stage, chunk) => {
+                column.chunk = Arc::new(chunk);
+                column.status = stage;
+            }
+            // The column may have left the view while meshing
+            StageResult::Meshed(layers) => {
+                if column.target == ChunkStatus::Mesh {
+                    column.status = ChunkStatus::Mesh;
+                    finished.push((key, layers));
+                }
+            }
         }
-    });
+    }
 
-    for (chunk_pos, chunk, mesh) in finished {
-        // chunk_pos = (i32, i32, layer)
-        if let Some(&entity) = chunk_manager.loaded_chunks.get(&chunk_pos) {
-            let mesh_handle = meshes.add(mesh);
-            let material_handle = materials.add(StandardMaterial {
-                base_color: Color::srgb(0.5, 0.8, 0.5),
-                unlit: fullbright.0,
-                emissive: if fullbright.0 {
-                    LinearRgba::from(Color::WHITE)
-                } else {
-                    LinearRgba::from(Color::BLACK)
-                },
-                ..default()
-            });
-
-            commands.entity(entity)
-                .insert(ChunkComponent(chunk))
-                .insert(Mesh3d(mesh_handle))
-                .insert(MeshMaterial3d(material_handle));
+    for ((chunk_x, chunk_z), layers) in finished {
+        // Only columns showing up for the first time fade in, remeshes swap in place
+        let fade = settings.fade_in > 0.0
+            && (0..num_vertical_chunks()).all(|layer| !manager.loaded_chunks.contains_key(&(chunk_x, chunk_z, layer)));
+        manager.despawn_layers(&mut commands, chunk_x, chunk_z);
+        for (layer, chunk_meshes) in layers.into_iter().enumerate() {
+            let layer = layer as i32;
+            let entity = spawn_vertical_chunk_layer(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &chunk_materials,
+                (chunk_x, chunk_z),
+                layer,
+                chunk_meshes,
+                fade,
+            );
+            if let Some(entity) = entity {
+                manager.loaded_chunks.insert((chunk_x, chunk_z, layer), entity);
+            }
         }
     }
 }
 
+/// Rebuild the meshes of slices changed by block edits in the background,
+/// swapping each slice once its new mesh is done
+pub(crate) fn remesh_dirty_chunks(
+    mut commands: Commands,
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    chunk_materials: Res<ChunkMaterials>,
+) {
+    let manager = &mut *chunk_manager;
+
+    // Swap in finished meshes
+    let mut finished = Vec::new();
+    manager.remesh_tasks.retain(|&key, task| match future::block_on(future::poll_once(task)) {
+        Some(chunk_meshes) => {
+            finished.push((key, chunk_meshes));
+            false
+        }
+        None => true,
+    });
+    for (key, chunk_meshes) in finished {
+        let (chunk_x, chunk_z, layer) = key;
+        if manager.status(chunk_x, chunk_z) != ChunkStatus::Mesh {
+            continue;
+        }
+        if let Some(old) = manager.loaded_chunks.remove(&key) {
+            commands.entity(old).despawn();
+        }
+        let entity = spawn_vertical_chunk_layer(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &chunk_materials,
+            (chunk_x, chunk_z),
+            layer,
+            chunk_meshes,
+            false,
+        );
+        if let Some(entity) = entity {
+            manager.loaded_chunks.insert(key, entity);
+        }
+    }
+
+    // Start remeshing dirty slices
+    let mut dirty: Vec<_> = manager.dirty.iter().copied().collect();
+    if let Some(center) = manager.center {
+        dirty.sort_by_key(|(x, z, _)| (x - center.0).pow(2) + (z - center.1).pow(2));
+    }
+    let thread_pool = AsyncComputeTaskPool::get();
 
+    for key in dirty {
+        if manager.remesh_tasks.len() >= MAX_REMESH_TASKS {
+            break;
+        }
+        let (chunk_x, chunk_z, layer) = key;
+        let Some(column) = manager.columns.get(&(chunk_x, chunk_z)) else {
+            manager.dirty.remove(&key);
+            continue;
+        };
+        // A running mesh task saw the old blocks, wait for it and mesh again
+        if column.task.is_some() || manager.remesh_tasks.contains_key(&key) {
+            continue;
+        }
+        manager.dirty.remove(&key);
+        // Not meshed yet, the first mesh picks up the change anyway
+        if column.status != ChunkStatus::Mesh {
+            continue;
+        }
+
+        let area = manager.neighborhood(chunk_x, chunk_z);
+        let task = thread_pool.spawn(async move { build_vertical_chunk_mesh(&area, layer as usize * VERTICAL_CHUNK_HEIGHT) });
+        manager.remesh_tasks.insert(key, task);
+    }
+}
 
+/// What a child mesh of a slice entity holds
+enum SlicePart {
+    Terrain,
+    Water,
+    Buried,
+}
 
+/// Spawn the entities of one slice, `None` if all its meshes are empty.
+/// With `fade` every mesh starts on a see-through copy of its material.
+#[allow(clippy::too_many_arguments)]
 fn spawn_vertical_chunk_layer(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
+    materials: &mut ResMut<Assets<ChunkMaterial>>,
+    chunk_materials: &ChunkMaterials,
     chunk_pos: (i32, i32),
     layer: i32,
-    seed: u64,
-    fullbright: bool,
-    xray: bool,
-    chunk_manager: &mut ChunkManager,
-) {
+    chunk_meshes: ChunkMeshes,
+    fade: bool,
+) -> Option<Entity> {
+    let ChunkMeshes { opaque, cave, highlight, buried, water } = chunk_meshes;
+    let parts = [
+        (opaque, &chunk_materials.opaque, SlicePart::Terrain),
+        // Cave walls and x-ray highlights are separate so x-ray can restyle them
+        (cave, &chunk_materials.cave, SlicePart::Terrain),
+        (highlight, &chunk_materials.highlight, SlicePart::Terrain),
+        (buried, &chunk_materials.highlight, SlicePart::Buried),
+        // Water uses the shared transparent material
+        (water, &chunk_materials.water, SlicePart::Water),
+    ];
+    if parts.iter().all(|(mesh, _, _)| mesh.count_vertices() == 0) {
+        return None;
+    }
+
     let chunk_world_x = chunk_pos.0 as f32 * CHUNK_SIZE as f32;
     let chunk_world_z = chunk_pos.1 as f32 * CHUNK_SIZE as f32;
 
     // world Y position for this vertical slice
     let chunk_world_y = -(HEIGHT_BELOW as f32) + layer as f32 * VERTICAL_CHUNK_HEIGHT as f32;
 
-    // Placeholder mesh for this slice only
-    let mut mesh = Mesh::from(Cuboid::new(
-        CHUNK_SIZE as f32,
-        VERTICAL_CHUNK_HEIGHT as f32,
-        CHUNK_SIZE as f32,
-    ));
-    mesh.translate_by(Vec3::new(0.0, VERTICAL_CHUNK_HEIGHT as f32 / 2.0, 0.0));
-    let placeholder_mesh = meshes.add(mesh);
-
-    let placeholder_material = materials.add(StandardMaterial {
-        base_color: Color::srgba(0.0, 0.0, 0.0, 0.0), // fully transparent
-        alpha_mode: AlphaMode::Blend,                // enable transparency
-        unlit: true,                                 // ignore lighting
-        ..default()
-    });
-
-
-
-    let entity = commands.spawn((
-        ChunkComponent(Chunk::empty()),
-        Mesh3d(placeholder_mesh),
-        MeshMaterial3d(placeholder_material),
-        Transform::from_xyz(chunk_world_x, chunk_world_y, chunk_world_z),
-        GlobalTransform::default(),
-        Visibility::default(),
-    )).id();
-
-    chunk_manager.loaded_chunks.insert((chunk_pos.0, chunk_pos.1, layer), entity);
+    let parent = commands
+        .spawn((Transform::from_xyz(chunk_world_x, chunk_world_y, chunk_world_z), Visibility::default()))
+        .id();
 
-    // Async mesh generation for this vertical slice
-    let thread_pool = AsyncComputeTaskPool::get();
-    let task = thread_pool.spawn(async move {
-        let chunk = Chunk::new(chunk_pos.0, chunk_pos.1, seed);
-        let mesh = build_vertical_chunk_mesh(&chunk, layer as usize * VERTICAL_CHUNK_HEIGHT);
-        ((chunk_pos.0, chunk_pos.1, layer), chunk, mesh)
-    });
+    for (mesh, shared, part) in parts {
+        if mesh.count_vertices() == 0 {
+            continue;
+        }
+        let faded = materials.get(shared).filter(|_| fade).map(|base| faded_material(base, 0.0));
+        let material = match faded {
+            Some(faded) => materials.add(faded),
+            None => shared.clone(),
+        };
+        let mut child = commands.spawn((Mesh3d(meshes.add(mesh)), MeshMaterial3d(material), Transform::default()));
+        if fade {
+            child.insert(ChunkFade::new(shared.clone()));
+        }
+        match part {
+            SlicePart::Terrain => {}
+            SlicePart::Water => {
+                child.insert(ChunkWater);
+            }
+            // Hidden until x-ray picks it up
+            SlicePart::Buried => {
+                child.insert((XRayOnly, Visibility::Hidden));
+            }
+        }
+        let child = child.id();
+        commands.entity(parent).add_child(child);
+    }
 
-    chunk_manager.load_queue.push(ChunkLoadTask { task });
+    Some(parent)
 }
@@ -0,0 +1,235 @@
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::prelude::*;
+use bevy::render::render_resource::AsBindGroup;
+use bevy::shader::ShaderRef;
+
+use crate::utils::input::{Action, Actions};
+use crate::utils::light::{Fullbright, RenderSettings};
+
+const CHUNK_SHADER_PATH: &str = "shaders/chunk.wgsl";
+
+/// Material of every chunk mesh
+pub type ChunkMaterial = ExtendedMaterial<StandardMaterial, SkyLight>;
+
+/// Chunk meshes carry their block colors in the vertex colors and their light in
+/// the uv channels, skylight at full daylight strength. The shader scales skylight
+/// by `scale`, so the time of day changes without remeshing anything.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct SkyLight {
+    #[uniform(100)]
+    pub scale: f32,
+}
+
+impl MaterialExtension for SkyLight {
+    fn fragment_shader() -> ShaderRef {
+        CHUNK_SHADER_PATH.into()
+    }
+}
+
+fn chunk_material(base: StandardMaterial) -> ChunkMaterial {
+    ChunkMaterial { base, extension: SkyLight { scale: 1.0 } }
+}
+
+/// Stone turns see-through, caves and highlight blocks stand out
+#[derive(Resource, Default)]
+pub struct XRay(pub bool);
+
+/// Render toggles that change how every chunk is drawn
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderModes {
+    pub fullbright: bool,
+    pub xray: bool,
+}
+
+/// Materials shared by all chunk slices. Render modes edit them in place,
+/// so every loaded chunk switches on the same frame.
+#[derive(Resource)]
+pub struct ChunkMaterials {
+    pub opaque: Handle<ChunkMaterial>,
+    pub cave: Handle<ChunkMaterial>,
+    pub highlight: Handle<ChunkMaterial>,
+    pub water: Handle<ChunkMaterial>,
+}
+
+impl ChunkMaterials {
+    /// Skylight strength of every chunk, 1 at full daylight
+    pub fn set_sky_light(&self, materials: &mut Assets<ChunkMaterial>, scale: f32) {
+        for handle in [&self.opaque, &self.cave, &self.highlight, &self.water] {
+            // Only touch changed materials, every change uploads them again
+            if materials.get(handle).is_some_and(|material| material.extension.scale != scale)
+                && let Some(material) = materials.get_mut(handle)
+            {
+                material.extension.scale = scale;
+            }
+        }
+    }
+}
+
+impl FromWorld for ChunkMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let modes = RenderModes {
+            fullbright: world.get_resource::<Fullbright>().is_some_and(|f| f.0),
+            xray: world.get_resource::<XRay>().is_some_and(|x| x.0),
+        };
+        let mut materials = world.resource_mut::<Assets<ChunkMaterial>>();
+        Self {
+            opaque: materials.add(chunk_material(opaque_material(modes))),
+            cave: materials.add(chunk_material(cave_material(modes))),
+            highlight: materials.add(chunk_material(highlight_material(modes))),
+            water: materials.add(chunk_material(water_material(modes))),
+        }
+    }
+}
+
+fn opaque_material(modes: RenderModes) -> StandardMaterial {
+    if modes.xray {
+        // Barely there stone
+        return StandardMaterial {
+            base_color: Color::srgba(1.0, 1.0, 1.0, 0.06),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        };
+    }
+    StandardMaterial {
+        base_color: Color::WHITE, // block colors come from the mesh vertex colors
+        unlit: modes.fullbright,
+        emissive: if modes.fullbright {
+            LinearRgba::from(Color::WHITE)
+        } else {
+            LinearRgba::from(Color::BLACK)
+        },
+        ..default()
+    }
+}
+
+fn cave_material(modes: RenderModes) -> StandardMaterial {
+    if modes.xray {
+        // Cave walls as a tinted shell
+        return StandardMaterial {
+            base_color: Color::srgba(0.5, 0.8, 1.0, 0.3),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        };
+    }
+    opaque_material(modes)
+}
+
+fn highlight_material(modes: RenderModes) -> StandardMaterial {
+    if modes.xray {
+        // Full color, drawn before the see-through stone
+        return StandardMaterial {
+            base_color: Color::WHITE,
+            unlit: true,
+            ..default()
+        };
+    }
+    opaque_material(modes)
+}
+
+fn water_material(modes: RenderModes) -> StandardMaterial {
+    StandardMaterial {
+        base_color: if modes.xray { Color::srgba(1.0, 1.0, 1.0, 0.15) } else { Color::WHITE },
+        alpha_mode: AlphaMode::Blend,
+        perceptual_roughness: 0.1,
+        unlit: modes.fullbright || modes.xray,
+        ..default()
+    }
+}
+
+/// Rebuild the shared materials when a render mode changes
+pub(crate) fn update_chunk_materials(
+    fullbright: Res<Fullbright>,
+    xray: Res<XRay>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    if !fullbright.is_changed() && !xray.is_changed() {
+        return;
+    }
+    let modes = RenderModes { fullbright: fullbright.0, xray: xray.0 };
+
+    let updates = [
+        (&chunk_materials.opaque, opaque_material(modes)),
+        (&chunk_materials.cave, cave_material(modes)),
+        (&chunk_materials.highlight, highlight_material(modes)),
+        (&chunk_materials.water, water_material(modes)),
+    ];
+    for (handle, new) in updates {
+        if let Some(material) = materials.get_mut(handle) {
+            material.base = new;
+        }
+    }
+}
+
+/// Mesh of a newly loaded slice fading in on its own copy of a shared material.
+/// Swapped back to the shared material once the fade is done.
+#[derive(Component)]
+pub struct ChunkFade {
+    elapsed: f32,
+    shared: Handle<ChunkMaterial>,
+}
+
+impl ChunkFade {
+    pub fn new(shared: Handle<ChunkMaterial>) -> Self {
+        Self { elapsed: 0.0, shared }
+    }
+}
+
+/// `base` blended in at `t` (0..1) of its own alpha
+pub fn faded_material(base: &ChunkMaterial, t: f32) -> ChunkMaterial {
+    let mut material = base.clone();
+    material.base.base_color.set_alpha(base.base.base_color.alpha() * t);
+    if material.base.alpha_mode == AlphaMode::Opaque {
+        material.base.alpha_mode = AlphaMode::Blend;
+    }
+    material
+}
+
+/// Fade new slices in over `RenderSettings::fade_in` seconds
+pub(crate) fn fade_in_chunks(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<RenderSettings>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut fading: Query<(Entity, &mut ChunkFade, &MeshMaterial3d<ChunkMaterial>)>,
+) {
+    for (entity, mut fade, material) in &mut fading {
+        fade.elapsed += time.delta_secs();
+        let t = if settings.fade_in > 0.0 { fade.elapsed / settings.fade_in } else { 1.0 };
+        if t >= 1.0 {
+            commands.entity(entity).remove::<ChunkFade>().insert(MeshMaterial3d(fade.shared.clone()));
+            continue;
+        }
+        // Copied every frame so render mode and skylight changes show up during the fade too
+        let Some(base) = materials.get(&fade.shared).cloned() else {
+            continue;
+        };
+        if let Some(material) = materials.get_mut(material) {
+            *material = faded_material(&base, t);
+        }
+    }
+}
+
+/// Mesh only drawn while x-ray is on
+#[derive(Component)]
+pub struct XRayOnly;
+
+/// Show x-ray-only meshes when x-ray is toggled, and on new slices
+pub(crate) fn update_xray_visibility(xray: Res<XRay>, mut meshes: Query<(Ref<XRayOnly>, &mut Visibility)>) {
+    let visibility = if xray.0 { Visibility::Inherited } else { Visibility::Hidden };
+    for (marker, mut current) in &mut meshes {
+        if xray.is_changed() || marker.is_added() {
+            *current = visibility;
+        }
+    }
+}
+
+/// `ToggleXRay` (X by default) toggles x-ray
+pub(crate) fn toggle_xray(actions: Res<Actions>, mut xray: ResMut<XRay>) {
+    if actions.just_pressed(Action::ToggleXRay) {
+        xray.0 = !xray.0;
+        info!("X-ray: {}", xray.0);
+    }
+}
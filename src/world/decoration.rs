@@ -0,0 +1,298 @@
+use bevy::math::IVec3;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW, TOTAL_HEIGHT};
+use crate::world::seed::chunk_rng;
+use crate::world::terrain::Terrain;
+use crate::world::voxel::{Chunk, Voxel};
+
+const DECORATION_SALT: u64 = 0x6465_636F; // "deco"
+
+/// How far a feature may reach from its origin column. Decoration replays the
+/// features of the 3x3 surrounding chunks, so anything wider would get cut off.
+pub const MAX_FEATURE_REACH: i32 = CHUNK_SIZE as i32;
+
+/// Something bigger than a single column that gets placed on the surface
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Feature {
+    Tree {
+        min_trunk: u32,
+        max_trunk: u32,
+        leaf_radius: u32,
+    },
+    Boulder {
+        min_radius: u32,
+        max_radius: u32,
+    },
+    Hut {
+        width: u32,
+        depth: u32,
+        height: u32,
+    },
+}
+
+/// One feature type, as written in the world preset
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecorationConfig {
+    pub feature: Feature,
+    /// Placement attempts per chunk
+    pub count_per_chunk: u32,
+    /// Chance for each attempt to actually place the feature
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+}
+
+fn default_chance() -> f32 {
+    1.0
+}
+
+pub fn default_decorations() -> Vec<DecorationConfig> {
+    vec![
+        DecorationConfig {
+            feature: Feature::Tree { min_trunk: 4, max_trunk: 6, leaf_radius: 2 },
+            count_per_chunk: 3,
+            chance: 0.6,
+        },
+        DecorationConfig {
+            feature: Feature::Boulder { min_radius: 1, max_radius: 2 },
+            count_per_chunk: 1,
+            chance: 0.15,
+        },
+        DecorationConfig {
+            feature: Feature::Hut { width: 5, depth: 5, height: 4 },
+            count_per_chunk: 1,
+            chance: 0.02,
+        },
+    ]
+}
+
+/// Write access to a single chunk using world coordinates.
+/// Writes that land outside the chunk are dropped, the neighbour places them itself.
+pub struct ChunkWriter<'a> {
+    chunk: &'a mut Chunk,
+    origin_x: i32,
+    origin_z: i32,
+}
+
+impl<'a> ChunkWriter<'a> {
+    pub fn new(chunk: &'a mut Chunk, chunk_x: i32, chunk_z: i32) -> Self {
+        Self {
+            chunk,
+            origin_x: chunk_x * CHUNK_SIZE as i32,
+            origin_z: chunk_z * CHUNK_SIZE as i32,
+        }
+    }
+
+    fn slot(&mut self, pos: IVec3) -> Option<&mut u8> {
+        let x = pos.x - self.origin_x;
+        let y = pos.y + HEIGHT_BELOW as i32;
+        let z = pos.z - self.origin_z;
+        if x < 0 || z < 0 || y < 0 || x >= CHUNK_SIZE as i32 || z >= CHUNK_SIZE as i32 || y >= TOTAL_HEIGHT as i32 {
+            return None;
+        }
+        Some(&mut self.chunk.data[y as usize][z as usize][x as usize])
+    }
+
+    pub fn set(&mut self, pos: IVec3, voxel: Voxel) {
+        if let Some(slot) = self.slot(pos) {
+            *slot = voxel as u8;
+        }
+    }
+
+    pub fn set_if_air(&mut self, pos: IVec3, voxel: Voxel) {
+        if let Some(slot) = self.slot(pos) {
+            if *slot == Voxel::Air as u8 {
+                *slot = voxel as u8;
+            }
+        }
+    }
+}
+
+impl Feature {
+    /// Place the feature standing on the ground at `origin`.
+    /// Must consume `rng` the same way no matter which chunk is being written,
+    /// so never branch on what's already in the chunk.
+    pub fn place(&self, origin: IVec3, rng: &mut StdRng, out: &mut ChunkWriter) {
+        match *self {
+            Feature::Tree { min_trunk, max_trunk, leaf_radius } => {
+                let trunk = rng.random_range(min_trunk..=max_trunk.max(min_trunk)) as i32;
+                let r = (leaf_radius as i32).min(MAX_FEATURE_REACH);
+                let top = origin.y + trunk;
+
+                // Leaf blob, narrowing towards the top
+                for dy in -2..=1 {
+                    let radius = if dy == 1 { (r - 1).max(1) } else { r };
+                    for dx in -radius..=radius {
+                        for dz in -radius..=radius {
+                            let corner = dx.abs() == radius && dz.abs() == radius;
+                            // Always roll so the RNG stream doesn't depend on the shape
+                            let trim = rng.random_bool(0.5);
+                            if corner && trim {
+                                continue;
+                            }
+                            out.set_if_air(IVec3::new(origin.x + dx, top + dy, origin.z + dz), Voxel::Leaves);
+                        }
+                    }
+                }
+
+                for y in origin.y..top {
+                    out.set(IVec3::new(origin.x, y, origin.z), Voxel::Log);
+                }
+            }
+            Feature::Boulder { min_radius, max_radius } => {
+                let r = (rng.random_range(min_radius..=max_radius.max(min_radius)) as i32).min(MAX_FEATURE_REACH);
+                for dx in -r..=r {
+                    for dy in -r..=r {
+                        for dz in -r..=r {
+                            if dx * dx + dy * dy + dz * dz <= r * r {
+                                out.set_if_air(origin + IVec3::new(dx, dy, dz), Voxel::Solid);
+                            }
+                        }
+                    }
+                }
+            }
+            Feature::Hut { width, depth, height } => {
+                let w = (width as i32).clamp(3, MAX_FEATURE_REACH);
+                let d = (depth as i32).clamp(3, MAX_FEATURE_REACH);
+                let h = (height as i32).max(2);
+                let door_on_x = rng.random_bool(0.5);
+
+                for dx in 0..w {
+                    for dz in 0..d {
+                        let edge_x = dx == 0 || dx == w - 1;
+                        let edge_z = dz == 0 || dz == d - 1;
+
+                        // Floor, levels the ground under the hut
+                        out.set(origin + IVec3::new(dx, -1, dz), Voxel::Planks);
+
+                        for dy in 0..h {
+                            let pos = origin + IVec3::new(dx, dy, dz);
+                            let door = dy < 2
+                                && if door_on_x { dz == 0 && dx == w / 2 } else { dx == 0 && dz == d / 2 };
+
+                            if edge_x && edge_z {
+                                out.set(pos, Voxel::Log);
+                            } else if (edge_x || edge_z) && !door {
+                                out.set(pos, Voxel::Planks);
+                            } else {
+                                out.set(pos, Voxel::Air);
+                            }
+                        }
+
+                        // Flat roof
+                        out.set(origin + IVec3::new(dx, h, dz), Voxel::Planks);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Place features into a chunk that already has its base terrain.
+/// Features from the surrounding chunks are replayed too, with the same per-chunk
+/// seeds, so the parts that cross the border line up without reading neighbour data.
+pub fn decorate(
+    chunk: &mut Chunk,
+    chunk_x: i32,
+    chunk_z: i32,
+    seed: u64,
+    terrain: &Terrain,
+    decorations: &[DecorationConfig],
+) {
+    let mut out = ChunkWriter::new(chunk, chunk_x, chunk_z);
+
+    // Fixed world order, so overlapping features win the same way in every chunk
+    for source_x in chunk_x - 1..=chunk_x + 1 {
+        for source_z in chunk_z - 1..=chunk_z + 1 {
+            for (i, deco) in decorations.iter().enumerate() {
+                let mut rng = chunk_rng(seed, source_x, source_z, DECORATION_SALT.wrapping_add(i as u64));
+
+                for _ in 0..deco.count_per_chunk {
+                    let x = rng.random_range(0..CHUNK_SIZE as i32);
+                    let z = rng.random_range(0..CHUNK_SIZE as i32);
+                    if !rng.random_bool(deco.chance.clamp(0.0, 1.0) as f64) {
+                        continue;
+                    }
+
+                    let world_x = source_x * CHUNK_SIZE as i32 + x;
+                    let world_z = source_z * CHUNK_SIZE as i32 + z;
+                    let column = terrain.column(world_x, world_z);
+                    if column.water_level.is_some() || column.river.is_some_and(|r| r.in_channel) {
+                        continue; // nothing grows under water or in river beds
+                    }
+
+                    deco.feature.place(IVec3::new(world_x, column.surface + 1, world_z), &mut rng, &mut out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 12345;
+    const TREE: Feature = Feature::Tree { min_trunk: 4, max_trunk: 6, leaf_radius: 2 };
+
+    /// Leaves spill from chunk (0, 0) into chunk (1, 0)
+    const EDGE_TREE: IVec3 = IVec3::new(CHUNK_SIZE as i32 - 1, 10, 5);
+
+    /// Place the edge tree into both chunks, `first` is generated first.
+    /// Returns both chunks and the next roll of each chunk's RNG.
+    fn place_edge_tree(first: i32, prefill: &[IVec3]) -> ([Box<Chunk>; 2], [u64; 2]) {
+        let mut chunks = [Box::new(Chunk::empty()), Box::new(Chunk::empty())];
+        for (chunk_x, chunk) in chunks.iter_mut().enumerate() {
+            let mut out = ChunkWriter::new(chunk, chunk_x as i32, 0);
+            for &pos in prefill {
+                out.set(pos, Voxel::Solid);
+            }
+        }
+
+        let mut next = [0; 2];
+        for chunk_x in [first, 1 - first] {
+            let mut rng = chunk_rng(SEED, 0, 0, DECORATION_SALT);
+            let mut out = ChunkWriter::new(&mut chunks[chunk_x as usize], chunk_x, 0);
+            TREE.place(EDGE_TREE, &mut rng, &mut out);
+            next[chunk_x as usize] = rng.random();
+        }
+        (chunks, next)
+    }
+
+    fn voxel(chunk: &Chunk, chunk_x: i32, pos: IVec3) -> Voxel {
+        let x = (pos.x - chunk_x * CHUNK_SIZE as i32) as usize;
+        Voxel::from_u8(chunk.data[(pos.y + HEIGHT_BELOW as i32) as usize][pos.z as usize][x])
+    }
+
+    #[test]
+    fn tree_on_chunk_edge_is_the_same_whichever_chunk_comes_first() {
+        let (forward, _) = place_edge_tree(0, &[]);
+        let (backward, _) = place_edge_tree(1, &[]);
+        assert!(forward[0].data == backward[0].data);
+        assert!(forward[1].data == backward[1].data);
+
+        // Trunk on one side, leaves on both
+        assert_eq!(voxel(&forward[0], 0, EDGE_TREE), Voxel::Log);
+        let leaves = |chunk: &Chunk| chunk.data.iter().flatten().flatten().filter(|&&v| v == Voxel::Leaves as u8).count();
+        assert!(leaves(&forward[0]) > 0);
+        assert!(leaves(&forward[1]) > 0);
+    }
+
+    #[test]
+    fn tree_uses_the_same_rolls_whatever_is_in_the_chunk() {
+        let (_, empty) = place_edge_tree(0, &[]);
+        // Terrain in the way of the leaves on the far side
+        let top = EDGE_TREE.y + 4;
+        let blocked = [IVec3::new(CHUNK_SIZE as i32, top, 5), IVec3::new(CHUNK_SIZE as i32 + 1, top - 1, 4)];
+        let (chunks, filled) = place_edge_tree(1, &blocked);
+
+        assert_eq!(empty[0], empty[1]);
+        assert_eq!(empty, filled);
+        for pos in blocked {
+            assert_eq!(voxel(&chunks[1], 1, pos), Voxel::Solid);
+        }
+    }
+}
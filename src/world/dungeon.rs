@@ -0,0 +1,354 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::input::{Action, Actions};
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW};
+use crate::world::decoration::ChunkWriter;
+use crate::world::seed::chunk_rng;
+use crate::world::voxel::{Chunk, Voxel};
+
+const DUNGEON_SALT: u64 = 0x6475_6E67_656F_6E; // "dungeon"
+
+/// Room-and-corridor dungeons, at most one per region of `region_size` x `region_size` chunks
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DungeonConfig {
+    pub enabled: bool,
+    /// Region size in chunks, a dungeon never leaves its region
+    pub region_size: u32,
+    /// Chance for a region to have a dungeon
+    pub chance: f32,
+    /// Rooms are laid out on a `grid` x `grid` layout, at most one per cell
+    pub grid: u32,
+    /// Size of one layout cell in blocks
+    pub cell_size: u32,
+    pub min_room: u32,
+    pub max_room: u32,
+    pub room_height: u32,
+    /// World y range for the dungeon floor
+    pub min_floor_y: i32,
+    pub max_floor_y: i32,
+}
+
+impl Default for DungeonConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            region_size: 6,
+            chance: 0.5,
+            grid: 4,
+            cell_size: 12,
+            min_room: 4,
+            max_room: 9,
+            room_height: 4,
+            min_floor_y: -(HEIGHT_BELOW as i32) + 2,
+            max_floor_y: -8,
+        }
+    }
+}
+
+/// Inclusive block box in world coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockBox {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl BlockBox {
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.cmpge(self.min).all() && pos.cmple(self.max).all()
+    }
+
+    pub fn center(&self) -> IVec3 {
+        (self.min + self.max) / 2
+    }
+
+    fn union(&self, other: &BlockBox) -> BlockBox {
+        BlockBox { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+}
+
+/// Generated layout of one dungeon, for carving and for gameplay queries
+#[derive(Clone, Debug)]
+pub struct Dungeon {
+    pub bounds: BlockBox,
+    pub rooms: Vec<BlockBox>,
+    pub corridors: Vec<BlockBox>,
+}
+
+impl Dungeon {
+    /// Lay out the dungeon of a region, `None` if the region has none.
+    /// Only depends on the seed and the region, so every chunk sees the same layout.
+    pub fn generate(seed: u64, config: &DungeonConfig, region: IVec2) -> Option<Dungeon> {
+        if !config.enabled {
+            return None;
+        }
+        let mut rng = chunk_rng(seed, region.x, region.y, DUNGEON_SALT);
+        if !rng.random_bool(config.chance.clamp(0.0, 1.0) as f64) {
+            return None;
+        }
+
+        let region_blocks = (config.region_size.max(1) * CHUNK_SIZE as u32) as i32;
+        // Room of at least 2 plus a wall on each side, smaller cells would leave no valid room size
+        let cell = config.cell_size.max(4) as i32;
+        let grid = (config.grid.max(1) as i32).min(region_blocks / cell);
+        if grid == 0 {
+            return None;
+        }
+        let max_room = (config.max_room as i32).clamp(2, cell - 2);
+        let min_room = (config.min_room as i32).clamp(2, max_room);
+        let height = (config.room_height as i32).max(2);
+
+        let max_floor = config.max_floor_y.min(-height - 1);
+        let min_floor = config.min_floor_y.max(-(HEIGHT_BELOW as i32) + 1).min(max_floor);
+        let floor_y = rng.random_range(min_floor..=max_floor);
+
+        // Place the layout somewhere inside the region
+        let span = grid * cell;
+        let origin_x = region.x * region_blocks + rng.random_range(0..=region_blocks - span);
+        let origin_z = region.y * region_blocks + rng.random_range(0..=region_blocks - span);
+
+        let mut rooms: Vec<BlockBox> = Vec::new();
+        for gz in 0..grid {
+            for gx in 0..grid {
+                // Always roll, then keep at least the first room
+                let keep = rng.random_bool(0.65);
+                let w = rng.random_range(min_room..=max_room);
+                let d = rng.random_range(min_room..=max_room);
+                let x = origin_x + gx * cell + rng.random_range(1..=cell - w - 1);
+                let z = origin_z + gz * cell + rng.random_range(1..=cell - d - 1);
+                if keep || rooms.is_empty() {
+                    rooms.push(BlockBox {
+                        min: IVec3::new(x, floor_y, z),
+                        max: IVec3::new(x + w - 1, floor_y + height - 1, z + d - 1),
+                    });
+                }
+            }
+        }
+
+        // Connect every room to the closest room before it, that's a spanning tree
+        let mut corridors = Vec::new();
+        for i in 1..rooms.len() {
+            let a = rooms[i].center();
+            let Some(b) = rooms[..i]
+                .iter()
+                .map(|room| room.center())
+                .min_by_key(|c| (c.x - a.x).abs() + (c.z - a.z).abs())
+            else {
+                continue;
+            };
+
+            // L shaped, x leg first, two blocks high
+            let corner = IVec3::new(b.x, floor_y, a.z);
+            corridors.push(BlockBox {
+                min: IVec3::new(a.x.min(corner.x), floor_y, a.z),
+                max: IVec3::new(a.x.max(corner.x), floor_y + 1, a.z),
+            });
+            corridors.push(BlockBox {
+                min: IVec3::new(b.x, floor_y, corner.z.min(b.z)),
+                max: IVec3::new(b.x, floor_y + 1, corner.z.max(b.z)),
+            });
+        }
+
+        let bounds = rooms.iter().chain(&corridors).fold(rooms[0], |acc, b| acc.union(b));
+        Some(Dungeon { bounds, rooms, corridors })
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        self.bounds.contains(pos) && self.rooms.iter().chain(&self.corridors).any(|b| b.contains(pos))
+    }
+}
+
+/// Region a chunk belongs to
+pub fn region_of_chunk(config: &DungeonConfig, chunk_x: i32, chunk_z: i32) -> IVec2 {
+    let size = config.region_size.max(1) as i32;
+    IVec2::new(chunk_x.div_euclid(size), chunk_z.div_euclid(size))
+}
+
+/// Carve the dungeon of this chunk's region, rooms get a plank floor and a glowstone light
+pub fn carve_dungeons(chunk: &mut Chunk, chunk_x: i32, chunk_z: i32, seed: u64, config: &DungeonConfig) {
+    let Some(dungeon) = Dungeon::generate(seed, config, region_of_chunk(config, chunk_x, chunk_z)) else {
+        return;
+    };
+
+    let mut out = ChunkWriter::new(chunk, chunk_x, chunk_z);
+    let chunk_min = IVec3::new(chunk_x * CHUNK_SIZE as i32, i32::MIN, chunk_z * CHUNK_SIZE as i32);
+    let chunk_max = IVec3::new(chunk_min.x + CHUNK_SIZE as i32 - 1, i32::MAX, chunk_min.z + CHUNK_SIZE as i32 - 1);
+
+    for (b, is_room) in dungeon.rooms.iter().map(|r| (r, true)).chain(dungeon.corridors.iter().map(|c| (c, false))) {
+        // Only the part of the box inside this chunk
+        let min = b.min.max(chunk_min);
+        let max = b.max.min(chunk_max);
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                if is_room {
+                    out.set(IVec3::new(x, b.min.y - 1, z), Voxel::Planks);
+                }
+                for y in min.y..=max.y {
+                    out.set(IVec3::new(x, y, z), Voxel::Air);
+                }
+            }
+        }
+    }
+
+    // A light in the middle of every ceiling
+    for room in &dungeon.rooms {
+        let center = room.center();
+        out.set(IVec3::new(center.x, room.max.y + 1, center.z), Voxel::Glowstone);
+    }
+}
+
+/// Dungeon queries for gameplay code (loot, spawning, locate)
+#[derive(Resource, Clone)]
+pub struct DungeonLocator {
+    pub seed: u64,
+    pub config: DungeonConfig,
+}
+
+impl DungeonLocator {
+    pub fn dungeon_in_region(&self, region: IVec2) -> Option<Dungeon> {
+        Dungeon::generate(self.seed, &self.config, region)
+    }
+
+    /// Dungeon the position is inside of, if any
+    pub fn dungeon_at(&self, pos: IVec3) -> Option<Dungeon> {
+        let chunk_x = pos.x.div_euclid(CHUNK_SIZE as i32);
+        let chunk_z = pos.z.div_euclid(CHUNK_SIZE as i32);
+        self.dungeon_in_region(region_of_chunk(&self.config, chunk_x, chunk_z))
+            .filter(|d| d.contains(pos))
+    }
+
+    /// Closest dungeon within `max_regions` rings of regions around `pos`
+    pub fn nearest(&self, pos: IVec3, max_regions: i32) -> Option<Dungeon> {
+        let chunk_x = pos.x.div_euclid(CHUNK_SIZE as i32);
+        let chunk_z = pos.z.div_euclid(CHUNK_SIZE as i32);
+        let center = region_of_chunk(&self.config, chunk_x, chunk_z);
+        let dist = |d: &Dungeon| d.bounds.center().as_vec3().distance_squared(pos.as_vec3());
+
+        let mut best: Option<Dungeon> = None;
+        for ring in 0..=max_regions {
+            for dx in -ring..=ring {
+                for dz in -ring..=ring {
+                    if dx.abs() != ring && dz.abs() != ring {
+                        continue; // inner rings are done already
+                    }
+                    if let Some(d) = self.dungeon_in_region(center + IVec2::new(dx, dz)) {
+                        if best.as_ref().is_none_or(|b| dist(&d) < dist(b)) {
+                            best = Some(d);
+                        }
+                    }
+                }
+            }
+            // Anything in a further ring is at least this far away
+            let ring_distance = (ring * self.config.region_size as i32 * CHUNK_SIZE as i32) as f32;
+            if best.as_ref().is_some_and(|b| dist(b) <= ring_distance * ring_distance) {
+                break;
+            }
+        }
+        best
+    }
+}
+
+/// `LocateDungeon` (L by default) logs the nearest dungeon, like a "locate" command
+pub(crate) fn locate_dungeon(
+    actions: Res<Actions>,
+    locator: Option<Res<DungeonLocator>>,
+    camera: Query<&Transform, With<Camera3d>>,
+) {
+    if !actions.just_pressed(Action::LocateDungeon) {
+        return;
+    }
+    let (Some(locator), Ok(transform)) = (locator, camera.single()) else {
+        return;
+    };
+
+    let pos = transform.translation.floor().as_ivec3();
+    if locator.dungeon_at(pos).is_some() {
+        info!("Inside a dungeon");
+        return;
+    }
+    match locator.nearest(pos, 8) {
+        Some(d) => info!(
+            "Nearest dungeon at {:?}, {} rooms, {:.0} blocks away",
+            d.bounds.center(),
+            d.rooms.len(),
+            d.bounds.center().as_vec3().distance(pos.as_vec3())
+        ),
+        None => info!("No dungeon nearby"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::constants::TOTAL_HEIGHT;
+
+    const SEED: u64 = 12345;
+
+    /// Always a dungeon, on a 2x2 chunk region so its rooms cross chunk borders
+    fn config() -> DungeonConfig {
+        DungeonConfig { region_size: 2, chance: 1.0, grid: 3, cell_size: 12, ..Default::default() }
+    }
+
+    fn solid_chunk() -> Box<Chunk> {
+        let mut chunk = Box::new(Chunk::empty());
+        chunk.data = [[[Voxel::Solid as u8; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT];
+        chunk
+    }
+
+    /// Carve the four chunks of region (0, 0) in the order given
+    fn carve_all(order: &[(i32, i32)]) -> Vec<((i32, i32), Box<Chunk>)> {
+        let mut chunks: Vec<_> = order
+            .iter()
+            .map(|&(chunk_x, chunk_z)| {
+                let mut chunk = solid_chunk();
+                carve_dungeons(&mut chunk, chunk_x, chunk_z, SEED, &config());
+                ((chunk_x, chunk_z), chunk)
+            })
+            .collect();
+        chunks.sort_by_key(|(key, _)| *key);
+        chunks
+    }
+
+    #[test]
+    fn layout_only_depends_on_seed_and_region() {
+        let a = Dungeon::generate(SEED, &config(), IVec2::ZERO).unwrap();
+        let b = Dungeon::generate(SEED, &config(), IVec2::ZERO).unwrap();
+        assert_eq!(a.rooms, b.rooms);
+        assert_eq!(a.corridors, b.corridors);
+        assert_eq!(a.bounds, b.bounds);
+    }
+
+    #[test]
+    fn dungeon_is_the_same_in_any_chunk_order() {
+        let mut order = vec![(0, 0), (1, 0), (0, 1), (1, 1)];
+        let forward = carve_all(&order);
+        order.reverse();
+        let backward = carve_all(&order);
+        for ((key, a), (_, b)) in forward.iter().zip(&backward) {
+            assert!(a.data == b.data, "chunk {key:?} differs");
+        }
+
+        // Every room and corridor block is carved, whichever chunk it fell in
+        let dungeon = Dungeon::generate(SEED, &config(), IVec2::ZERO).unwrap();
+        let mut chunks_hit = Vec::new();
+        for b in dungeon.rooms.iter().chain(&dungeon.corridors) {
+            for x in b.min.x..=b.max.x {
+                for y in b.min.y..=b.max.y {
+                    for z in b.min.z..=b.max.z {
+                        let key = (x.div_euclid(CHUNK_SIZE as i32), z.div_euclid(CHUNK_SIZE as i32));
+                        let (_, chunk) = forward.iter().find(|(k, _)| *k == key).unwrap();
+                        let voxel = chunk.data[(y + HEIGHT_BELOW as i32) as usize]
+                            [z.rem_euclid(CHUNK_SIZE as i32) as usize][x.rem_euclid(CHUNK_SIZE as i32) as usize];
+                        assert_eq!(voxel, Voxel::Air as u8, "{x} {y} {z} not carved");
+                        if !chunks_hit.contains(&key) {
+                            chunks_hit.push(key);
+                        }
+                    }
+                }
+            }
+        }
+        assert!(chunks_hit.len() > 1, "the dungeon never crossed a chunk border");
+    }
+}
@@ -0,0 +1,316 @@
+use bevy::math::Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::world::constants::HEIGHT_ABOVE;
+use crate::world::seed::chunk_rng;
+use crate::world::terrain::Terrain;
+
+const EROSION_SALT: u64 = 0x6572_6F64_65; // "erode"
+
+/// Fewest eroded tiles kept around, before the generated area is known
+const MIN_CACHED_TILES: usize = 64;
+
+/// Droplet-based hydraulic erosion, run on fixed tiles of the heightmap
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErosionConfig {
+    pub enabled: bool,
+    /// Size of the square tiles erosion runs on
+    pub tile_size: u32,
+    /// Extra border simulated around each tile, blended with the neighbour tiles.
+    /// Must be smaller than `tile_size`.
+    pub margin: u32,
+    pub droplets_per_tile: u32,
+    pub max_lifetime: u32,
+    /// 0 = droplets follow the slope exactly, 1 = they never turn
+    pub inertia: f32,
+    pub sediment_capacity: f32,
+    pub min_capacity: f32,
+    pub erode_speed: f32,
+    pub deposit_speed: f32,
+    pub evaporate_speed: f32,
+    pub gravity: f32,
+}
+
+impl Default for ErosionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tile_size: 64,
+            margin: 24,
+            droplets_per_tile: 8000,
+            max_lifetime: 30,
+            inertia: 0.05,
+            sediment_capacity: 4.0,
+            min_capacity: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporate_speed: 0.01,
+            gravity: 4.0,
+        }
+    }
+}
+
+/// Height change erosion made to one tile, including its margin
+struct ErodedTile {
+    origin_x: i32,
+    origin_z: i32,
+    size: i32,
+    delta: Vec<f32>,
+}
+
+/// Eroded tiles shared by all chunk tasks of a world.
+/// Every tile only depends on the seed and its position, so it doesn't matter
+/// which chunk asks for it first.
+pub struct ErosionCache {
+    config: ErosionConfig,
+    tiles: Mutex<(HashMap<(i32, i32), Arc<ErodedTile>>, VecDeque<(i32, i32)>)>,
+    /// Most tiles kept, enough to cover everything around the camera
+    capacity: AtomicUsize,
+}
+
+impl ErosionCache {
+    pub fn new(config: &ErosionConfig) -> Self {
+        let mut config = config.clone();
+        config.tile_size = config.tile_size.max(8);
+        config.margin = config.margin.min(config.tile_size - 1);
+        Self {
+            config,
+            tiles: Mutex::new((HashMap::new(), VecDeque::new())),
+            capacity: AtomicUsize::new(MIN_CACHED_TILES),
+        }
+    }
+
+    /// Keep every tile within `reach` blocks of the camera, so chunks at the
+    /// edge of a large render distance don't erode the same tiles over and over
+    pub fn set_reach(&self, reach: i32) {
+        // Tiles across the area, plus the ones its margins overlap on either side
+        let span = (2 * (reach.max(0) as u32 + self.config.margin)).div_ceil(self.config.tile_size) + 2;
+        let capacity = ((span * span) as usize).max(MIN_CACHED_TILES);
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Eroded height of a column. Tiles overlapping the column are blended,
+    /// each weighted by how far the column is from the edge of that tile's region.
+    pub fn height(&self, terrain: &Terrain, world_x: i32, world_z: i32) -> f32 {
+        let base = terrain.base_height(world_x, world_z);
+        let tile = self.config.tile_size as i32;
+        let margin = self.config.margin as i32;
+
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        for tile_x in (world_x - margin).div_euclid(tile)..=(world_x + margin).div_euclid(tile) {
+            for tile_z in (world_z - margin).div_euclid(tile)..=(world_z + margin).div_euclid(tile) {
+                let eroded = self.tile(terrain, tile_x, tile_z);
+                let lx = world_x - eroded.origin_x;
+                let lz = world_z - eroded.origin_z;
+
+                let edge = lx.min(lz).min(eroded.size - 1 - lx).min(eroded.size - 1 - lz);
+                let w = ((edge + 1) as f32 / (margin + 1) as f32).min(1.0);
+                if w <= 0.0 {
+                    continue;
+                }
+                sum += eroded.delta[(lz * eroded.size + lx) as usize] * w;
+                weights += w;
+            }
+        }
+
+        if weights > 0.0 { base + sum / weights } else { base }
+    }
+
+    fn tile(&self, terrain: &Terrain, tile_x: i32, tile_z: i32) -> Arc<ErodedTile> {
+        if let Some(tile) = self.tiles.lock().unwrap().0.get(&(tile_x, tile_z)) {
+            return tile.clone();
+        }
+
+        // Erode outside the lock, two tasks may do the same tile but get the same result
+        let tile = Arc::new(self.erode_tile(terrain, tile_x, tile_z));
+
+        let mut guard = self.tiles.lock().unwrap();
+        let (tiles, order) = &mut *guard;
+        if tiles.insert((tile_x, tile_z), tile.clone()).is_none() {
+            order.push_back((tile_x, tile_z));
+        }
+        while order.len() > self.capacity.load(Ordering::Relaxed) {
+            if let Some(old) = order.pop_front() {
+                tiles.remove(&old);
+            }
+        }
+        tile
+    }
+
+    fn erode_tile(&self, terrain: &Terrain, tile_x: i32, tile_z: i32) -> ErodedTile {
+        let c = &self.config;
+        let margin = c.margin as i32;
+        let size = c.tile_size as i32 + margin * 2;
+        let origin_x = tile_x * c.tile_size as i32 - margin;
+        let origin_z = tile_z * c.tile_size as i32 - margin;
+
+        let mut map = HeightGrid {
+            size,
+            heights: Vec::with_capacity((size * size) as usize),
+        };
+        for z in 0..size {
+            for x in 0..size {
+                map.heights.push(terrain.base_height(origin_x + x, origin_z + z));
+            }
+        }
+        let original = map.heights.clone();
+
+        let mut rng = chunk_rng(terrain.seed(), tile_x, tile_z, EROSION_SALT);
+        let limit = (size - 1) as f32;
+
+        for _ in 0..c.droplets_per_tile {
+            let mut pos = Vec2::new(rng.random_range(0.0..limit), rng.random_range(0.0..limit));
+            let mut dir = Vec2::ZERO;
+            let mut speed = 1.0f32;
+            let mut water = 1.0f32;
+            let mut sediment = 0.0f32;
+
+            for _ in 0..c.max_lifetime {
+                let (height, gradient) = map.sample(pos);
+
+                dir = dir * c.inertia - gradient * (1.0 - c.inertia);
+                if dir.length_squared() < 1e-8 {
+                    break; // flat, nowhere to go
+                }
+                dir = dir.normalize();
+                let old_pos = pos;
+                pos += dir;
+                if pos.x < 0.0 || pos.y < 0.0 || pos.x >= limit || pos.y >= limit {
+                    break;
+                }
+
+                let delta_height = map.sample(pos).0 - height;
+                let capacity = (-delta_height * speed * water * c.sediment_capacity).max(c.min_capacity);
+
+                if sediment > capacity || delta_height > 0.0 {
+                    // Uphill: fill the pit behind us, otherwise drop what we can't carry
+                    let amount = if delta_height > 0.0 {
+                        delta_height.min(sediment)
+                    } else {
+                        (sediment - capacity) * c.deposit_speed
+                    };
+                    sediment -= amount;
+                    map.add(old_pos, amount);
+                } else {
+                    // Never dig deeper than the step we just went down
+                    let amount = ((capacity - sediment) * c.erode_speed).min(-delta_height);
+                    sediment += amount;
+                    map.add(old_pos, -amount);
+                }
+
+                speed = (speed * speed - delta_height * c.gravity).max(0.0).sqrt();
+                water *= 1.0 - c.evaporate_speed;
+            }
+        }
+
+        let delta = map
+            .heights
+            .iter()
+            .zip(&original)
+            .map(|(h, o)| h.clamp(0.0, (HEIGHT_ABOVE - 1) as f32) - o)
+            .collect();
+
+        ErodedTile { origin_x, origin_z, size, delta }
+    }
+}
+
+/// Square height grid with bilinear reads and writes
+struct HeightGrid {
+    size: i32,
+    heights: Vec<f32>,
+}
+
+impl HeightGrid {
+    fn cell(&self, pos: Vec2) -> (usize, f32, f32) {
+        let x = pos.x.floor() as i32;
+        let z = pos.y.floor() as i32;
+        ((z * self.size + x) as usize, pos.x - x as f32, pos.y - z as f32)
+    }
+
+    /// Height and (uphill) gradient at a point inside the grid
+    fn sample(&self, pos: Vec2) -> (f32, Vec2) {
+        let (i, u, v) = self.cell(pos);
+        let s = self.size as usize;
+        let nw = self.heights[i];
+        let ne = self.heights[i + 1];
+        let sw = self.heights[i + s];
+        let se = self.heights[i + s + 1];
+
+        let gradient = Vec2::new(
+            (ne - nw) * (1.0 - v) + (se - sw) * v,
+            (sw - nw) * (1.0 - u) + (se - ne) * u,
+        );
+        let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+        (height, gradient)
+    }
+
+    /// Spread `amount` over the four corners of the cell around `pos`
+    fn add(&mut self, pos: Vec2, amount: f32) {
+        let (i, u, v) = self.cell(pos);
+        let s = self.size as usize;
+        self.heights[i] += amount * (1.0 - u) * (1.0 - v);
+        self.heights[i + 1] += amount * u * (1.0 - v);
+        self.heights[i + s] += amount * (1.0 - u) * v;
+        self.heights[i + s + 1] += amount * u * v;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::preset::WorldPreset;
+
+    const SEED: u64 = 12345;
+
+    fn config() -> ErosionConfig {
+        ErosionConfig {
+            enabled: true,
+            tile_size: 16,
+            margin: 4,
+            droplets_per_tile: 400,
+            ..Default::default()
+        }
+    }
+
+    /// Columns on both sides of the edge between tile (0, 0) and tile (1, 0)
+    fn edge_columns() -> Vec<(i32, i32)> {
+        (0..16).flat_map(|z| [(15, z), (16, z)]).collect()
+    }
+
+    fn heights(columns: &[(i32, i32)]) -> Vec<f32> {
+        let terrain = Terrain::new(SEED, &WorldPreset::default(), None);
+        let cache = ErosionCache::new(&config());
+        columns.iter().map(|&(x, z)| cache.height(&terrain, x, z)).collect()
+    }
+
+    #[test]
+    fn shared_edge_is_the_same_whichever_tile_erodes_first() {
+        let columns = edge_columns();
+        // Reversed, the right tile's columns come first and erode first
+        let mut reversed = columns.clone();
+        reversed.reverse();
+
+        let forward = heights(&columns);
+        let mut backward = heights(&reversed);
+        backward.reverse();
+        assert_eq!(forward, backward);
+
+        let terrain = Terrain::new(SEED, &WorldPreset::default(), None);
+        assert!(
+            columns.iter().zip(&forward).any(|(&(x, z), h)| *h != terrain.base_height(x, z)),
+            "erosion didn't change the edge at all"
+        );
+    }
+
+    #[test]
+    fn repeat_runs_erode_the_same() {
+        let columns = edge_columns();
+        assert_eq!(heights(&columns), heights(&columns));
+    }
+}
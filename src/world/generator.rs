@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::utils::config::cli_arg;
+use crate::world::builtin_generators::{CheckerboardGenerator, DebugGridGenerator, FlatLayer, SuperflatGenerator, VoidGenerator, default_flat_layers};
+use crate::world::erosion::ErosionCache;
+use crate::world::heightmap::{HeightmapGenerator, HeightmapSettings};
+use crate::world::decoration::decorate;
+use crate::world::dungeon::carve_dungeons;
+use crate::world::metadata::WorldMetadata;
+use crate::world::ore::generate_ores;
+use crate::world::pipeline::{ChunkNeighborhood, ChunkStatus};
+use crate::world::preset::WorldPreset;
+use crate::world::structure::{load_structures, place_structures, StructureTemplate, STRUCTURES_DIR};
+use crate::world::terrain::Terrain;
+use crate::world::voxel::{Chunk, Voxel};
+
+/// Produces the voxel data of one chunk column. Runs on the async task pool.
+pub trait ChunkGenerator: Send + Sync {
+    /// Noise stage, the base blocks of a fresh chunk column
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk;
+
+    /// Surface, carver and feature stages. Generators that do everything
+    /// in `generate` leave this empty.
+    fn run_stage(&self, _stage: ChunkStatus, _chunk: &mut Chunk, _area: &ChunkNeighborhood) {}
+
+    /// Blocks around the camera that chunks get generated in, for sizing caches
+    fn set_reach(&self, _reach: i32) {}
+}
+
+/// Which generator a world uses, stored in the world metadata
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeneratorKind {
+    /// The regular Perlin terrain
+    #[default]
+    Noise,
+    /// Stack of flat layers, listed bottom to top starting at the bottom of the world
+    Superflat {
+        #[serde(default = "default_flat_layers")]
+        layers: Vec<FlatLayer>,
+    },
+    /// One block thick floor at y = 0 in a checker pattern
+    Checkerboard {
+        #[serde(default = "default_tile_size")]
+        tile_size: u32,
+    },
+    /// Nothing at all
+    Void,
+    /// Every block type laid out in rows
+    Debug,
+    /// Terrain from a greyscale image
+    Heightmap(HeightmapSettings),
+}
+
+fn default_tile_size() -> u32 {
+    4
+}
+
+impl GeneratorKind {
+    /// Generator picked by name on the command line, with default settings
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "noise" | "default" => Some(Self::Noise),
+            "superflat" | "flat" => Some(Self::Superflat { layers: default_flat_layers() }),
+            "checkerboard" => Some(Self::Checkerboard { tile_size: default_tile_size() }),
+            "void" => Some(Self::Void),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// The generator used for every chunk of the current world
+#[derive(Resource, Clone)]
+pub struct WorldGenerator(pub Arc<dyn ChunkGenerator>);
+
+impl WorldGenerator {
+    pub fn new(kind: &GeneratorKind, seed: u64, preset: &WorldPreset) -> Self {
+        let generator: Arc<dyn ChunkGenerator> = match kind {
+            GeneratorKind::Noise => Arc::new(NoiseGenerator::new(seed, preset)),
+            GeneratorKind::Superflat { layers } => Arc::new(SuperflatGenerator::new(layers.clone())),
+            GeneratorKind::Checkerboard { tile_size } => Arc::new(CheckerboardGenerator {
+                tile_size: (*tile_size).max(1) as i32,
+                blocks: [Voxel::Solid, Voxel::Sand],
+            }),
+            GeneratorKind::Void => Arc::new(VoidGenerator),
+            GeneratorKind::Debug => Arc::new(DebugGridGenerator),
+            GeneratorKind::Heightmap(settings) => match HeightmapGenerator::load(settings, seed, preset) {
+                Ok(generator) => Arc::new(generator),
+                Err(err) => {
+                    warn!("Failed to load heightmap: {}, using noise terrain", err);
+                    Arc::new(NoiseGenerator::new(seed, preset))
+                }
+            },
+        };
+        Self(generator)
+    }
+
+    /// Pick the generator from `--generator <name>` on the command line, otherwise
+    /// the one stored in the world metadata. The result is written back to `metadata`.
+    pub fn resolve(args: &[String], metadata: &mut WorldMetadata, seed: u64, preset: &WorldPreset) -> Self {
+        if let Some(name) = cli_arg(args, "--generator") {
+            match GeneratorKind::from_name(&name) {
+                Some(kind) => metadata.generator = kind,
+                None => warn!("Unknown generator '{}', using {:?}", name, metadata.generator),
+            }
+        }
+        info!("World generator: {:?}", metadata.generator);
+        Self::new(&metadata.generator, seed, preset)
+    }
+}
+
+/// Perlin terrain with water, rivers, ores and decorations
+pub struct NoiseGenerator {
+    pub seed: u64,
+    pub preset: WorldPreset,
+    /// Eroded tiles, shared between all chunk tasks
+    erosion: Option<Arc<ErosionCache>>,
+    structures: Vec<StructureTemplate>,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u64, preset: &WorldPreset) -> Self {
+        Self {
+            seed,
+            preset: preset.clone(),
+            erosion: preset.erosion.enabled.then(|| Arc::new(ErosionCache::new(&preset.erosion))),
+            structures: load_structures(STRUCTURES_DIR),
+        }
+    }
+}
+
+impl NoiseGenerator {
+    fn terrain(&self) -> Terrain {
+        Terrain::new(self.seed, &self.preset, self.erosion.clone())
+    }
+}
+
+impl ChunkGenerator for NoiseGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::empty();
+        self.terrain().fill_density(&mut chunk, chunk_x, chunk_z);
+        chunk
+    }
+
+    fn run_stage(&self, stage: ChunkStatus, chunk: &mut Chunk, area: &ChunkNeighborhood) {
+        let (chunk_x, chunk_z) = (area.chunk_x, area.chunk_z);
+        match stage {
+            ChunkStatus::Surface => self.terrain().fill_surface(chunk, chunk_x, chunk_z),
+            ChunkStatus::Carvers => carve_dungeons(chunk, chunk_x, chunk_z, self.seed, &self.preset.dungeons),
+            ChunkStatus::Features => {
+                let terrain = self.terrain();
+                generate_ores(chunk, chunk_x, chunk_z, self.seed, &self.preset.ores);
+                decorate(chunk, chunk_x, chunk_z, self.seed, &terrain, &self.preset.decorations);
+                place_structures(chunk, chunk_x, chunk_z, self.seed, &terrain, &self.structures);
+            }
+            _ => {}
+        }
+    }
+
+    fn set_reach(&self, reach: i32) {
+        if let Some(erosion) = &self.erosion {
+            erosion.set_reach(reach);
+        }
+    }
+}
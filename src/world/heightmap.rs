@@ -0,0 +1,229 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_ABOVE, HEIGHT_BELOW};
+use crate::world::generator::ChunkGenerator;
+use crate::world::preset::WorldPreset;
+use crate::world::seed::noise_seed;
+use crate::world::terrain::SAND_DEPTH;
+use crate::world::voxel::{Chunk, Voxel};
+
+const DETAIL_SALT: u64 = 0x6465_7461_696C; // "detail"
+
+/// What the world looks like past the edges of the image
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutsideMode {
+    /// Repeat the edge pixels forever
+    #[default]
+    Clamp,
+    /// Repeat the whole image
+    Tile,
+    /// No terrain at all outside the image
+    Void,
+}
+
+/// Small noise added on top of the image so large flat areas don't look painted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DetailNoise {
+    /// Max height change in blocks
+    pub amplitude: f32,
+    pub frequency: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HeightmapSettings {
+    /// Greyscale PNG (8 or 16 bit), or raw little-endian 16-bit samples (`.r16` / `.raw`)
+    pub path: String,
+    /// Width of a raw heightmap, raw files without it are assumed to be square
+    #[serde(default)]
+    pub raw_width: Option<u32>,
+    /// World y for black pixels
+    #[serde(default)]
+    pub min_height: i32,
+    /// World y for white pixels, capped at `HEIGHT_ABOVE - 1`
+    #[serde(default = "default_max_height")]
+    pub max_height: i32,
+    /// Horizontal scale, pixels are interpolated in between
+    #[serde(default = "default_blocks_per_pixel")]
+    pub blocks_per_pixel: f32,
+    #[serde(default)]
+    pub outside: OutsideMode,
+    #[serde(default)]
+    pub detail: Option<DetailNoise>,
+}
+
+fn default_max_height() -> i32 {
+    HEIGHT_ABOVE as i32 - 1
+}
+
+fn default_blocks_per_pixel() -> f32 {
+    1.0
+}
+
+/// Terrain taken from a greyscale image, pixel (0, 0) sits at the world origin
+/// and image rows run along +z
+pub struct HeightmapGenerator {
+    width: u32,
+    height: u32,
+    /// Pixel values normalized to 0..1, row major
+    samples: Vec<f32>,
+    settings: HeightmapSettings,
+    sea_level: i32,
+    beach_height: i32,
+    detail_noise: Perlin,
+}
+
+impl HeightmapGenerator {
+    pub fn load(settings: &HeightmapSettings, seed: u64, preset: &WorldPreset) -> Result<Self, String> {
+        let bytes = fs::read(&settings.path).map_err(|err| format!("{}: {}", settings.path, err))?;
+
+        let extension = Path::new(&settings.path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let (width, height, samples) = if extension == "r16" || extension == "raw" {
+            decode_raw16(&bytes, settings.raw_width)?
+        } else {
+            decode_image(&bytes, &extension)?
+        };
+        info!("Loaded heightmap {} ({}x{})", settings.path, width, height);
+
+        Ok(Self {
+            width,
+            height,
+            samples,
+            settings: settings.clone(),
+            sea_level: preset.sea_level,
+            beach_height: preset.beach_height,
+            detail_noise: Perlin::new(noise_seed(seed, DETAIL_SALT)),
+        })
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Option<f32> {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (x, y) = match self.settings.outside {
+            OutsideMode::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+            OutsideMode::Tile => (x.rem_euclid(w), y.rem_euclid(h)),
+            OutsideMode::Void => {
+                if x < 0 || y < 0 || x >= w || y >= h {
+                    return None;
+                }
+                (x, y)
+            }
+        };
+        Some(self.samples[(y * w + x) as usize])
+    }
+
+    /// Bilinear sample in image space, `None` outside the image in void mode
+    fn sample(&self, world_x: i32, world_z: i32) -> Option<f32> {
+        let scale = self.settings.blocks_per_pixel.max(0.001);
+        let fx = world_x as f32 / scale;
+        let fz = world_z as f32 / scale;
+        let (x0, z0) = (fx.floor() as i64, fz.floor() as i64);
+        let (tx, tz) = (fx - x0 as f32, fz - z0 as f32);
+
+        let a = self.pixel(x0, z0)?;
+        let b = self.pixel(x0 + 1, z0).unwrap_or(a);
+        let c = self.pixel(x0, z0 + 1).unwrap_or(a);
+        let d = self.pixel(x0 + 1, z0 + 1).unwrap_or(a);
+
+        let top = a + (b - a) * tx;
+        let bottom = c + (d - c) * tx;
+        Some(top + (bottom - top) * tz)
+    }
+
+    /// World y of the topmost solid block of a column
+    fn surface_height(&self, world_x: i32, world_z: i32) -> Option<i32> {
+        let value = self.sample(world_x, world_z)?;
+        let min = self.settings.min_height.max(0);
+        let max = self.settings.max_height.clamp(min, HEIGHT_ABOVE as i32 - 1);
+        let mut h = min as f32 + value * (max - min) as f32;
+
+        if let Some(detail) = &self.settings.detail {
+            let f = detail.frequency;
+            h += self.detail_noise.get([world_x as f64 * f, world_z as f64 * f]) as f32 * detail.amplitude;
+        }
+
+        Some((h.round() as i32).clamp(0, HEIGHT_ABOVE as i32 - 1))
+    }
+}
+
+impl ChunkGenerator for HeightmapGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = Chunk::empty();
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let world_x = chunk_x * CHUNK_SIZE as i32 + x as i32;
+                let world_z = chunk_z * CHUNK_SIZE as i32 + z as i32;
+                let Some(surface) = self.surface_height(world_x, world_z) else {
+                    continue;
+                };
+
+                // Same surface rules as the noise terrain: sand near and under water
+                let top = if surface <= self.sea_level + self.beach_height { Voxel::Sand } else { Voxel::Solid };
+                let water_top = self.sea_level.max(surface);
+
+                for world_y in -(HEIGHT_BELOW as i32)..=water_top {
+                    let block = if world_y > surface {
+                        Voxel::Water
+                    } else if world_y > surface - SAND_DEPTH {
+                        top
+                    } else {
+                        Voxel::Solid
+                    };
+                    chunk.data[(world_y + HEIGHT_BELOW as i32) as usize][z][x] = block as u8;
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+/// PNG and friends through `bevy_image`, only the red channel is used
+fn decode_image(bytes: &[u8], extension: &str) -> Result<(u32, u32, Vec<f32>), String> {
+    let image = Image::from_buffer(
+        bytes,
+        ImageType::Extension(extension),
+        CompressedImageFormats::NONE,
+        false,
+        ImageSampler::Default,
+        RenderAssetUsages::default(),
+    )
+    .map_err(|err| err.to_string())?;
+
+    let (width, height) = (image.width(), image.height());
+    let mut samples = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let color = image.get_color_at(x, y).map_err(|err| err.to_string())?;
+            samples.push(color.to_linear().red.clamp(0.0, 1.0));
+        }
+    }
+    Ok((width, height, samples))
+}
+
+/// Headerless little-endian 16-bit samples
+fn decode_raw16(bytes: &[u8], raw_width: Option<u32>) -> Result<(u32, u32, Vec<f32>), String> {
+    let count = bytes.len() / 2;
+    let width = raw_width.unwrap_or((count as f64).sqrt() as u32);
+    if width == 0 || count % width as usize != 0 {
+        return Err(format!("raw heightmap of {} samples doesn't fit width {}", count, width));
+    }
+    let height = (count / width as usize) as u32;
+
+    let samples = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as f32 / u16::MAX as f32)
+        .collect();
+    Ok((width, height, samples))
+}
@@ -0,0 +1,296 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::world::constants::{CHUNK_SIZE, TOTAL_HEIGHT};
+use crate::world::pipeline::ChunkNeighborhood;
+use crate::world::voxel::Voxel;
+
+/// Light level of open sky, every step through air costs one level
+pub const MAX_LIGHT: u8 = 15;
+
+/// Per-voxel light levels, same layout as `Chunk::data`
+pub type LightGrid = [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT];
+
+/// Per-voxel block light, red, green and blue levels packed into 4 bits each
+pub type BlockLightGrid = [[[u16; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT];
+
+const DIRECTIONS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+/// Skylight and the three block light colors spread independently
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    Sky,
+    /// 0 = red, 1 = green, 2 = blue
+    Block(usize),
+}
+
+impl LightChannel {
+    pub const ALL: [LightChannel; 4] = [
+        LightChannel::Sky,
+        LightChannel::Block(0),
+        LightChannel::Block(1),
+        LightChannel::Block(2),
+    ];
+}
+
+pub fn pack_rgb(rgb: [u8; 3]) -> u16 {
+    (rgb[0] as u16) << 8 | (rgb[1] as u16) << 4 | rgb[2] as u16
+}
+
+pub fn unpack_rgb(packed: u16) -> [u8; 3] {
+    [(packed >> 8) as u8 & 0xF, (packed >> 4) as u8 & 0xF, packed as u8 & 0xF]
+}
+
+impl Voxel {
+    /// Extra levels light loses passing through this block, `MAX_LIGHT` blocks it
+    pub fn light_opacity(self) -> u8 {
+        match self {
+            Voxel::Air => 0,
+            Voxel::Water => 2,
+            _ => MAX_LIGHT,
+        }
+    }
+
+    /// Red, green and blue light level given off by the block
+    pub fn light_emission(self) -> [u8; 3] {
+        match self {
+            Voxel::Torch => [14, 11, 7],
+            Voxel::Lava => [15, 8, 3],
+            Voxel::Glowstone => [15, 14, 10],
+            _ => [0; 3],
+        }
+    }
+
+    pub fn is_emissive(self) -> bool {
+        self.light_emission() != [0; 3]
+    }
+}
+
+/// Vertex color multiplier for a (possibly averaged) light level
+pub fn brightness(level: f32) -> f32 {
+    0.8f32.powf(MAX_LIGHT as f32 - level.clamp(0.0, MAX_LIGHT as f32))
+}
+
+/// Level a neighbour gets from a voxel lit at `level`, going in direction `dir`.
+/// Full skylight goes straight down through air without losing anything.
+fn spread(level: u8, dir: IVec3, into: Voxel, channel: LightChannel) -> u8 {
+    let opacity = into.light_opacity();
+    if opacity >= MAX_LIGHT {
+        0
+    } else if channel == LightChannel::Sky && level == MAX_LIGHT && dir == IVec3::NEG_Y && opacity == 0 {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1 + opacity)
+    }
+}
+
+/// Blocks of a chunk plus `MAX_LIGHT` blocks into each neighbour, so light coming
+/// in from the neighbours is included and the result only depends on the blocks
+pub struct LightVolume {
+    width: i32,
+    height: i32,
+    voxels: Vec<Voxel>,
+}
+
+impl LightVolume {
+    const MARGIN: i32 = MAX_LIGHT as i32;
+
+    pub fn new(area: &ChunkNeighborhood) -> Self {
+        let width = CHUNK_SIZE as i32 + Self::MARGIN * 2;
+        let height = TOTAL_HEIGHT as i32;
+
+        // Missing neighbours count as solid
+        let mut voxels = Vec::with_capacity((width * width * height) as usize);
+        for y in 0..height {
+            for z in 0..width {
+                for x in 0..width {
+                    voxels.push(area.voxel(x - Self::MARGIN, y, z - Self::MARGIN).unwrap_or(Voxel::Solid));
+                }
+            }
+        }
+        Self { width, height, voxels }
+    }
+
+    fn index(&self, p: IVec3) -> usize {
+        ((p.y * self.width + p.z) * self.width + p.x) as usize
+    }
+
+    fn contains(&self, p: IVec3) -> bool {
+        p.cmpge(IVec3::ZERO).all() && p.x < self.width && p.y < self.height && p.z < self.width
+    }
+
+    /// Breadth-first spread from already lit cells
+    fn flood(&self, light: &mut [u8], mut queue: VecDeque<IVec3>, channel: LightChannel) {
+        while let Some(p) = queue.pop_front() {
+            let level = light[self.index(p)];
+            for dir in DIRECTIONS {
+                let n = p + dir;
+                if !self.contains(n) {
+                    continue;
+                }
+                let new = spread(level, dir, self.voxels[self.index(n)], channel);
+                if new > light[self.index(n)] {
+                    light[self.index(n)] = new;
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+
+    /// Copy the center chunk out of a volume sized light buffer
+    fn center<T: Copy + Default>(&self, value: impl Fn(usize) -> T) -> [[[T; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT] {
+        let mut grid = [[[T::default(); CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT];
+        for (y, layer) in grid.iter_mut().enumerate() {
+            for (z, row) in layer.iter_mut().enumerate() {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    let p = IVec3::new(x as i32 + Self::MARGIN, y as i32, z as i32 + Self::MARGIN);
+                    *cell = value(self.index(p));
+                }
+            }
+        }
+        grid
+    }
+
+    /// Skylight: full light down to the first block that isn't air, then spread sideways
+    pub fn sky_light(&self) -> LightGrid {
+        let mut light = vec![0u8; self.voxels.len()];
+        let mut queue = VecDeque::new();
+        for z in 0..self.width {
+            for x in 0..self.width {
+                for y in (0..self.height).rev() {
+                    let p = IVec3::new(x, y, z);
+                    if self.voxels[self.index(p)] != Voxel::Air {
+                        break;
+                    }
+                    light[self.index(p)] = MAX_LIGHT;
+                    queue.push_back(p);
+                }
+            }
+        }
+        self.flood(&mut light, queue, LightChannel::Sky);
+        self.center(|i| light[i])
+    }
+
+    /// Colored light of emissive blocks, `None` if nothing in reach gives off light
+    pub fn block_light(&self) -> Option<Box<BlockLightGrid>> {
+        if !self.voxels.iter().any(|voxel| voxel.is_emissive()) {
+            return None;
+        }
+
+        let mut channels = Vec::new();
+        for c in 0..3 {
+            let mut light = vec![0u8; self.voxels.len()];
+            let mut queue = VecDeque::new();
+            for (i, voxel) in self.voxels.iter().enumerate() {
+                let level = voxel.light_emission()[c];
+                if level > 0 {
+                    light[i] = level;
+                    let i = i as i32;
+                    queue.push_back(IVec3::new(i % self.width, i / (self.width * self.width), i / self.width % self.width));
+                }
+            }
+            self.flood(&mut light, queue, LightChannel::Block(c));
+            channels.push(light);
+        }
+
+        Some(Box::new(self.center(|i| pack_rgb([channels[0][i], channels[1][i], channels[2][i]]))))
+    }
+}
+
+/// World-space block and light access for incremental relighting
+pub trait LightStorage {
+    /// `None` where nothing is loaded, light doesn't go there
+    fn voxel(&self, pos: IVec3) -> Option<Voxel>;
+    fn light(&self, pos: IVec3, channel: LightChannel) -> u8;
+    fn set_light(&mut self, pos: IVec3, channel: LightChannel, level: u8);
+    /// Top of the world, everything above is open sky
+    fn max_y(&self) -> i32;
+}
+
+/// Light a voxel gives itself: emission, or open sky for air at the top of the world
+fn source_level(storage: &impl LightStorage, pos: IVec3, channel: LightChannel) -> u8 {
+    let Some(voxel) = storage.voxel(pos) else {
+        return 0;
+    };
+    match channel {
+        LightChannel::Sky if pos.y == storage.max_y() && voxel == Voxel::Air => MAX_LIGHT,
+        LightChannel::Sky => 0,
+        LightChannel::Block(c) => voxel.light_emission()[c],
+    }
+}
+
+/// Fix up all light channels after the block at `pos` changed.
+/// Only the area that was lit through `pos`, or can be lit by it now, is touched.
+pub fn update_light(storage: &mut impl LightStorage, pos: IVec3) {
+    for channel in LightChannel::ALL {
+        relight(storage, pos, channel);
+    }
+}
+
+/// Clear everything lit through `pos`, then spread light back in from the edge
+/// of the cleared area and from `pos` itself
+fn relight(storage: &mut impl LightStorage, pos: IVec3, channel: LightChannel) {
+    let mut removal = VecDeque::new();
+    let mut refill = VecDeque::new();
+
+    let old = storage.light(pos, channel);
+    if old > 0 {
+        storage.set_light(pos, channel, 0);
+        removal.push_back((pos, old));
+    }
+
+    while let Some((p, level)) = removal.pop_front() {
+        for dir in DIRECTIONS {
+            let n = p + dir;
+            if storage.voxel(n).is_none() {
+                continue;
+            }
+            let neighbor = storage.light(n, channel);
+            if neighbor == 0 {
+                continue;
+            }
+            // Lit by us: clear it too. Otherwise it has its own source and relights the hole.
+            let straight_down = channel == LightChannel::Sky && dir == IVec3::NEG_Y && level == MAX_LIGHT && neighbor == MAX_LIGHT;
+            if neighbor < level || straight_down {
+                storage.set_light(n, channel, 0);
+                removal.push_back((n, neighbor));
+                // Light sources that got cleared shine again
+                let source = source_level(storage, n, channel);
+                if source > 0 {
+                    storage.set_light(n, channel, source);
+                    refill.push_back(n);
+                }
+            } else {
+                refill.push_back(n);
+            }
+        }
+    }
+
+    // Light coming back in, including `pos` itself
+    for dir in DIRECTIONS {
+        let n = pos + dir;
+        if storage.light(n, channel) > 0 {
+            refill.push_back(n);
+        }
+    }
+    let source = source_level(storage, pos, channel);
+    if source > storage.light(pos, channel) {
+        storage.set_light(pos, channel, source);
+        refill.push_back(pos);
+    }
+
+    while let Some(p) = refill.pop_front() {
+        let level = storage.light(p, channel);
+        for dir in DIRECTIONS {
+            let n = p + dir;
+            let Some(voxel) = storage.voxel(n) else {
+                continue;
+            };
+            let new = spread(level, dir, voxel, channel);
+            if new > storage.light(n, channel) {
+                storage.set_light(n, channel, new);
+                refill.push_back(n);
+            }
+        }
+    }
+}
@@ -4,7 +4,9 @@ use bevy::camera::primitives::MeshAabb;
 use bevy::prelude::*;
 use wgpu_types::PrimitiveTopology;
 use crate::world::voxel::{Chunk, Voxel};
-use crate::world::constants::{CHUNK_SIZE, TOTAL_HEIGHT, VERTICAL_CHUNK_HEIGHT};
+use crate::world::pipeline::ChunkNeighborhood;
+use crate::world::lighting::{brightness, MAX_LIGHT};
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW, TOTAL_HEIGHT, VERTICAL_CHUNK_HEIGHT};
 use bevy::mesh::{Mesh, Indices};
 
 #[derive(Clone, Copy)]
@@ -97,101 +99,212 @@ pub fn neighbor_is_air(
 
 
 
-pub(crate) fn build_chunk_mesh(chunk: &Chunk) -> Mesh {
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-    let mut indices = Vec::new();
-    let mut vertex_offset = 0u32;
+/// Meshes for one chunk (or vertical slice), water is kept apart so it can be drawn
+/// with its own transparent material after the terrain under it.
+/// Cave walls and x-ray highlights get their own meshes so x-ray can restyle them.
+pub(crate) struct ChunkMeshes {
+    pub opaque: Mesh,
+    /// Underground faces looking into cave air
+    pub cave: Mesh,
+    /// Ores and placed blocks
+    pub highlight: Mesh,
+    /// Faces of highlight blocks buried in stone, only drawn while x-ray is on
+    pub buried: Mesh,
+    pub water: Mesh,
+}
 
-    for y in 0..TOTAL_HEIGHT {
-        for z in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                if chunk.data[y][z][x] != Voxel::Solid as u8 {
-                    continue;
-                }
+/// Light of a vertex as brightness multipliers: sky, then block light red, green, blue
+type VertexLight = [f32; 4];
 
-                for face in CubeFace::all() {
-                    // Check if neighbor inside the chunk is air
-                    let neighbor_air = match face {
-                        CubeFace::Top => y + 1 >= TOTAL_HEIGHT || chunk.data[y + 1][z][x] == Voxel::Air as u8,
-                        CubeFace::Bottom => y == 0 || chunk.data[y - 1][z][x] == Voxel::Air as u8,
-                        CubeFace::Left => x == 0 || chunk.data[y][z][x - 1] == Voxel::Air as u8,
-                        CubeFace::Right => x + 1 >= CHUNK_SIZE || chunk.data[y][z][x + 1] == Voxel::Air as u8,
-                        CubeFace::Front => z + 1 >= CHUNK_SIZE || chunk.data[y][z + 1][x] == Voxel::Air as u8,
-                        CubeFace::Back => z == 0 || chunk.data[y][z - 1][x] == Voxel::Air as u8,
-                    };
+/// Drawn at full brightness whatever the time of day
+const FULL_LIGHT: VertexLight = [1.0; 4];
 
-                    if neighbor_air {
-                        let fv = face_vertices(face, x, y, z, vertex_offset);
-                        vertex_offset += fv.positions.len() as u32;
-                        positions.extend(fv.positions);
-                        normals.extend(fv.normals);
-                        indices.extend(fv.indices);
-                    }
-                }
-            }
-        }
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    light: Vec<VertexLight>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn push_face(&mut self, face: CubeFace, x: usize, y: usize, z: usize, color: [f32; 4], light: [VertexLight; 4]) {
+        let fv = face_vertices(face, x, y, z, self.positions.len() as u32);
+        self.positions.extend(fv.positions);
+        self.normals.extend(fv.normals);
+        self.colors.extend([color; 4]);
+        self.light.extend(light);
+        self.indices.extend(fv.indices);
     }
 
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_indices(Indices::U32(indices));
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        // The chunk shader reads the light from the uv channels, see `SkyLight`
+        let (sky_red, green_blue): (Vec<_>, Vec<_>) = self.light.iter().map(|l| ([l[0], l[1]], [l[2], l[3]])).unzip();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, sky_red);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, green_blue);
+        mesh.insert_indices(Indices::U32(self.indices));
 
-    mesh.compute_aabb();
-    mesh
+        mesh.compute_aabb();
+        mesh
+    }
 }
 
+/// Neighbour voxel, looked up in the surrounding chunks past the x/z edges.
+/// `None` if that chunk isn't there.
+fn neighbor(area: &ChunkNeighborhood, x: usize, y: usize, z: usize, face: CubeFace) -> Option<Voxel> {
+    let (x, y, z) = (x as i32, y as i32, z as i32);
+    match face {
+        CubeFace::Top => area.voxel(x, y + 1, z),
+        CubeFace::Bottom => area.voxel(x, y - 1, z),
+        CubeFace::Left => area.voxel(x - 1, y, z),
+        CubeFace::Right => area.voxel(x + 1, y, z),
+        CubeFace::Front => area.voxel(x, y, z + 1),
+        CubeFace::Back => area.voxel(x, y, z - 1),
+    }
+}
 
+fn face_normal(face: CubeFace) -> IVec3 {
+    match face {
+        CubeFace::Top => IVec3::Y,
+        CubeFace::Bottom => IVec3::NEG_Y,
+        CubeFace::Left => IVec3::NEG_X,
+        CubeFace::Right => IVec3::X,
+        CubeFace::Front => IVec3::Z,
+        CubeFace::Back => IVec3::NEG_Z,
+    }
+}
 
+/// Smooth light at one corner of a face: the average of the voxels in front of the
+/// face that touch the corner, opaque ones left out. Returns sky and (r, g, b) levels.
+fn corner_light(area: &ChunkNeighborhood, block: IVec3, face: CubeFace, corner: [f32; 3]) -> (f32, [f32; 3]) {
+    let normal = face_normal(face);
+    let front = block + normal;
 
-pub(crate) fn build_vertical_chunk_mesh(chunk: &Chunk, y_offset: usize) -> Mesh {
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-    let mut indices = Vec::new();
-    let mut vertex_offset = 0u32;
+    // The two axes of the face plane, stepping towards the corner
+    let mut steps = (0..3).filter(|&axis| normal[axis] == 0).map(|axis| {
+        let mut step = IVec3::ZERO;
+        step[axis] = if corner[axis] > block[axis] as f32 + 0.5 { 1 } else { -1 };
+        step
+    });
+    let (a, b) = (steps.next().unwrap_or_default(), steps.next().unwrap_or_default());
 
-    // Define vertical slice bounds
-    let start_y = y_offset;
-    let end_y = (y_offset + VERTICAL_CHUNK_HEIGHT).min(TOTAL_HEIGHT);
+    let open = |p: IVec3| area.voxel(p.x, p.y, p.z).is_none_or(|v| v.light_opacity() < MAX_LIGHT);
+    let side_a = open(front + a);
+    let side_b = open(front + b);
+    // The diagonal can't be seen past two solid sides
+    let samples = [
+        (front, true),
+        (front + a, side_a),
+        (front + b, side_b),
+        (front + a + b, open(front + a + b) && (side_a || side_b)),
+    ];
+
+    let mut sky = 0.0;
+    let mut rgb = [0.0; 3];
+    let mut count = 0.0;
+    for (p, used) in samples {
+        if !used {
+            continue;
+        }
+        sky += area.sky_light(p.x, p.y, p.z) as f32;
+        for (sum, level) in rgb.iter_mut().zip(area.block_light(p.x, p.y, p.z)) {
+            *sum += level as f32;
+        }
+        count += 1.0;
+    }
+    (sky / count, rgb.map(|sum| sum / count))
+}
+
+/// Light at each corner of a face. Skylight is kept at full daylight strength,
+/// the chunk material darkens it for the time of day.
+fn face_light(area: &ChunkNeighborhood, voxel: Voxel, x: usize, y: usize, z: usize, face: CubeFace) -> [VertexLight; 4] {
+    if voxel.is_emissive() {
+        return [FULL_LIGHT; 4];
+    }
+
+    let block = IVec3::new(x as i32, y as i32, z as i32);
+    let corners = face_vertices(face, x, y, z, 0).positions;
+    std::array::from_fn(|i| {
+        let (sky, rgb) = corner_light(area, block, face, corners[i]);
+        [brightness(sky), brightness(rgb[0]), brightness(rgb[1]), brightness(rgb[2])]
+    })
+}
+
+fn build_meshes(area: &ChunkNeighborhood, start_y: usize, end_y: usize, y_offset: usize) -> ChunkMeshes {
+    let chunk = area.center();
+    let mut opaque = MeshBuilder::default();
+    let mut cave = MeshBuilder::default();
+    let mut highlight = MeshBuilder::default();
+    let mut buried = MeshBuilder::default();
+    let mut water = MeshBuilder::default();
 
     for y in start_y..end_y {
         for z in 0..CHUNK_SIZE {
             for x in 0..CHUNK_SIZE {
-                if chunk.data[y][z][x] != Voxel::Solid as u8 {
+                let voxel = Voxel::from_u8(chunk.data[y][z][x]);
+                if voxel == Voxel::Air {
                     continue;
                 }
-
                 for face in CubeFace::all() {
-                    // Check if neighbor inside the chunk is air
-                    let neighbor_air = match face {
-                        CubeFace::Top => y + 1 >= TOTAL_HEIGHT || chunk.data[y + 1][z][x] == Voxel::Air as u8,
-                        CubeFace::Bottom => y == 0 || chunk.data[y - 1][z][x] == Voxel::Air as u8,
-                        CubeFace::Left => x == 0 || chunk.data[y][z][x - 1] == Voxel::Air as u8,
-                        CubeFace::Right => x + 1 >= CHUNK_SIZE || chunk.data[y][z][x + 1] == Voxel::Air as u8,
-                        CubeFace::Front => z + 1 >= CHUNK_SIZE || chunk.data[y][z + 1][x] == Voxel::Air as u8,
-                        CubeFace::Back => z == 0 || chunk.data[y][z - 1][x] == Voxel::Air as u8,
+                    let next = neighbor(area, x, y, z, face);
+                    let visible = if voxel == Voxel::Water {
+                        // Only the surface against air, missing neighbours stay closed
+                        next == Some(Voxel::Air)
+                    } else {
+                        next.is_none_or(Voxel::is_transparent)
                     };
-
-                    if neighbor_air {
-                        // Make vertex positions relative to this vertical chunk
-                        let fv = face_vertices(face, x, y - y_offset, z, vertex_offset);
-                        vertex_offset += fv.positions.len() as u32;
-                        positions.extend(fv.positions);
-                        normals.extend(fv.normals);
-                        indices.extend(fv.indices);
+                    // X-ray shows highlight blocks through the stone around them
+                    let is_buried = !visible && voxel.is_xray_highlight() && next.is_some_and(|n| !n.is_xray_highlight());
+                    if !visible && !is_buried {
+                        continue;
                     }
+
+                    let light = if next.is_some_and(|n| !n.is_transparent()) {
+                        [FULL_LIGHT; 4]
+                    } else {
+                        face_light(area, voxel, x, y, z, face)
+                    };
+
+                    // Make vertex positions relative to this vertical chunk
+                    let target = if voxel == Voxel::Water {
+                        &mut water
+                    } else if is_buried {
+                        &mut buried
+                    } else if voxel.is_xray_highlight() {
+                        &mut highlight
+                    } else if y < HEIGHT_BELOW && next == Some(Voxel::Air) {
+                        &mut cave
+                    } else {
+                        &mut opaque
+                    };
+                    target.push_face(face, x, y - y_offset, z, voxel.color().to_linear().to_f32_array(), light);
                 }
             }
         }
     }
 
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_indices(Indices::U32(indices));
+    ChunkMeshes {
+        opaque: opaque.build(),
+        cave: cave.build(),
+        highlight: highlight.build(),
+        buried: buried.build(),
+        water: water.build(),
+    }
+}
 
-    mesh.compute_aabb();
-    mesh
+pub(crate) fn build_chunk_mesh(area: &ChunkNeighborhood) -> ChunkMeshes {
+    build_meshes(area, 0, TOTAL_HEIGHT, 0)
 }
 
+pub(crate) fn build_vertical_chunk_mesh(area: &ChunkNeighborhood, y_offset: usize) -> ChunkMeshes {
+    // Define vertical slice bounds
+    let start_y = y_offset;
+    let end_y = (y_offset + VERTICAL_CHUNK_HEIGHT).min(TOTAL_HEIGHT);
+
+    build_meshes(area, start_y, end_y, y_offset)
+}
@@ -0,0 +1,30 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::config::{load_toml_or_default, save_toml};
+use crate::utils::time_of_day::TimeOfDay;
+use crate::world::generator::GeneratorKind;
+
+pub const METADATA_PATH: &str = "world.toml";
+
+/// Per-world state that has to survive a restart, stored in `world.toml`
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldMetadata {
+    /// Bit pattern of the `u64` seed, TOML only has signed integers
+    pub seed: Option<i64>,
+    /// The text the seed was made from, if it was given as text
+    pub seed_text: Option<String>,
+    pub generator: GeneratorKind,
+    pub time: TimeOfDay,
+}
+
+impl WorldMetadata {
+    pub fn load() -> Self {
+        load_toml_or_default(METADATA_PATH)
+    }
+
+    pub fn save(&self) {
+        save_toml(METADATA_PATH, self);
+    }
+}
@@ -1,6 +1,23 @@
 pub(crate) mod constants;
-mod voxel;
+pub(crate) mod voxel;
 mod mesher;
 pub(crate) mod ChunkPlugin;
 pub(crate) mod seed;
-pub(crate) mod chunk_manager;
\ No newline at end of file
+pub(crate) mod metadata;
+pub(crate) mod generator;
+pub(crate) mod builtin_generators;
+pub(crate) mod heightmap;
+pub(crate) mod chunk_manager;
+pub(crate) mod chunk_materials;
+pub(crate) mod pipeline;
+pub(crate) mod lighting;
+pub(crate) mod ore;
+pub(crate) mod preset;
+pub(crate) mod terrain;
+pub(crate) mod river;
+pub(crate) mod erosion;
+pub(crate) mod structure;
+pub(crate) mod decoration;
+pub(crate) mod dungeon;
+pub(crate) mod raycast;
+
@@ -0,0 +1,132 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_BELOW, TOTAL_HEIGHT};
+use crate::world::seed::chunk_rng;
+use crate::world::voxel::{Chunk, Voxel};
+
+const ORE_SALT: u64 = 0x6F72_6573; // "ores"
+
+/// How vein heights are picked inside `min_y..=max_y`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeightDistribution {
+    /// Every height is equally likely
+    #[default]
+    Uniform,
+    /// Most veins around the middle of the range, fading out towards both ends
+    Triangle,
+}
+
+/// One ore type, as written in the world preset
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OreConfig {
+    pub block: Voxel,
+    /// Only this block gets replaced by the ore
+    #[serde(default = "default_host")]
+    pub host: Voxel,
+    /// Number of blocks in a single vein
+    pub vein_size: u32,
+    pub veins_per_chunk: u32,
+    /// World y range (0 = ground level, negative = underground)
+    pub min_y: i32,
+    pub max_y: i32,
+    #[serde(default)]
+    pub distribution: HeightDistribution,
+}
+
+fn default_host() -> Voxel {
+    Voxel::Solid
+}
+
+pub fn default_ores() -> Vec<OreConfig> {
+    vec![
+        OreConfig {
+            block: Voxel::CoalOre,
+            host: Voxel::Solid,
+            vein_size: 12,
+            veins_per_chunk: 8,
+            min_y: -(HEIGHT_BELOW as i32),
+            max_y: 40,
+            distribution: HeightDistribution::Uniform,
+        },
+        OreConfig {
+            block: Voxel::IronOre,
+            host: Voxel::Solid,
+            vein_size: 8,
+            veins_per_chunk: 6,
+            min_y: -(HEIGHT_BELOW as i32),
+            max_y: 16,
+            distribution: HeightDistribution::Triangle,
+        },
+        OreConfig {
+            block: Voxel::GoldOre,
+            host: Voxel::Solid,
+            vein_size: 6,
+            veins_per_chunk: 2,
+            min_y: -(HEIGHT_BELOW as i32),
+            max_y: -8,
+            distribution: HeightDistribution::Triangle,
+        },
+        OreConfig {
+            block: Voxel::DiamondOre,
+            host: Voxel::Solid,
+            vein_size: 4,
+            veins_per_chunk: 1,
+            min_y: -(HEIGHT_BELOW as i32),
+            max_y: -20,
+            distribution: HeightDistribution::Uniform,
+        },
+    ]
+}
+
+/// Scatter ore veins into an already filled chunk
+pub fn generate_ores(chunk: &mut Chunk, chunk_x: i32, chunk_z: i32, seed: u64, ores: &[OreConfig]) {
+    for (i, ore) in ores.iter().enumerate() {
+        // Own RNG per ore, so adding an ore to the preset doesn't move the others
+        let mut rng = chunk_rng(seed, chunk_x, chunk_z, ORE_SALT.wrapping_add(i as u64));
+
+        let min_y = ore.min_y.max(-(HEIGHT_BELOW as i32));
+        let max_y = ore.max_y.min(TOTAL_HEIGHT as i32 - HEIGHT_BELOW as i32 - 1);
+        if min_y > max_y {
+            continue;
+        }
+
+        for _ in 0..ore.veins_per_chunk {
+            let world_y = match ore.distribution {
+                HeightDistribution::Uniform => rng.random_range(min_y..=max_y),
+                HeightDistribution::Triangle => {
+                    let half = (max_y - min_y) / 2;
+                    min_y + rng.random_range(0..=half) + rng.random_range(0..=(max_y - min_y - half))
+                }
+            };
+
+            let mut x = rng.random_range(0..CHUNK_SIZE as i32);
+            let mut y = world_y + HEIGHT_BELOW as i32;
+            let mut z = rng.random_range(0..CHUNK_SIZE as i32);
+
+            // Random walk, veins get clipped at the chunk edges
+            for _ in 0..ore.vein_size {
+                place_ore(chunk, x, y, z, ore);
+                match rng.random_range(0..6) {
+                    0 => x += 1,
+                    1 => x -= 1,
+                    2 => y += 1,
+                    3 => y -= 1,
+                    4 => z += 1,
+                    _ => z -= 1,
+                }
+            }
+        }
+    }
+}
+
+fn place_ore(chunk: &mut Chunk, x: i32, y: i32, z: i32, ore: &OreConfig) {
+    if x < 0 || z < 0 || y < 0 || x >= CHUNK_SIZE as i32 || z >= CHUNK_SIZE as i32 || y >= TOTAL_HEIGHT as i32 {
+        return;
+    }
+    let voxel = &mut chunk.data[y as usize][z as usize][x as usize];
+    if *voxel == ore.host as u8 {
+        *voxel = ore.block as u8;
+    }
+}
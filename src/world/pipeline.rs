@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use crate::world::constants::{CHUNK_SIZE, TOTAL_HEIGHT};
+use crate::world::generator::ChunkGenerator;
+use crate::world::lighting::{unpack_rgb, LightVolume, MAX_LIGHT};
+use crate::world::voxel::{Chunk, Voxel};
+
+/// Generation stages a chunk column goes through, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkStatus {
+    /// Nothing generated yet
+    Empty,
+    /// Base terrain shape: stone and caves
+    Noise,
+    /// Surface blocks and water
+    Surface,
+    /// Dungeons and other things cut out of the ground
+    Carvers,
+    /// Ores, decorations and structures
+    Features,
+    Light,
+    /// Meshes built and spawned
+    Mesh,
+}
+
+impl ChunkStatus {
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Empty => Some(Self::Noise),
+            Self::Noise => Some(Self::Surface),
+            Self::Surface => Some(Self::Carvers),
+            Self::Carvers => Some(Self::Features),
+            Self::Features => Some(Self::Light),
+            Self::Light => Some(Self::Mesh),
+            Self::Mesh => None,
+        }
+    }
+
+    /// Stage all 8 surrounding columns must have reached before this stage may run
+    pub fn neighbor_requirement(self) -> Option<Self> {
+        match self {
+            Self::Features => Some(Self::Carvers),
+            Self::Light => Some(Self::Features),
+            Self::Mesh => Some(Self::Light),
+            _ => None,
+        }
+    }
+}
+
+/// Read-only snapshot of a chunk column and its 8 neighbours, handed to stage tasks
+pub struct ChunkNeighborhood {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// Indexed by `(dz + 1) * 3 + (dx + 1)`, the middle one is the chunk itself
+    chunks: [Option<Arc<Chunk>>; 9],
+}
+
+impl ChunkNeighborhood {
+    pub fn new(chunk_x: i32, chunk_z: i32, chunks: [Option<Arc<Chunk>>; 9]) -> Self {
+        Self { chunk_x, chunk_z, chunks }
+    }
+
+    pub fn center(&self) -> &Chunk {
+        self.chunks[4].as_deref().expect("neighborhood without its center chunk")
+    }
+
+    pub fn get(&self, dx: i32, dz: i32) -> Option<&Chunk> {
+        self.chunks[((dz + 1) * 3 + dx + 1) as usize].as_deref()
+    }
+
+    /// Voxel at chunk-local coordinates, x and z may reach one chunk into the neighbours.
+    /// `None` for missing neighbours, above and below the world counts as air.
+    pub fn voxel(&self, x: i32, y: i32, z: i32) -> Option<Voxel> {
+        if y < 0 || y >= TOTAL_HEIGHT as i32 {
+            return Some(Voxel::Air);
+        }
+        let size = CHUNK_SIZE as i32;
+        let chunk = self.get(x.div_euclid(size), z.div_euclid(size))?;
+        Some(Voxel::from_u8(chunk.data[y as usize][z.rem_euclid(size) as usize][x.rem_euclid(size) as usize]))
+    }
+
+    /// Skylight at chunk-local coordinates like `voxel`, open sky above the world
+    /// and for missing neighbours
+    pub fn sky_light(&self, x: i32, y: i32, z: i32) -> u8 {
+        if y >= TOTAL_HEIGHT as i32 {
+            return MAX_LIGHT;
+        }
+        if y < 0 {
+            return 0;
+        }
+        let size = CHUNK_SIZE as i32;
+        match self.get(x.div_euclid(size), z.div_euclid(size)) {
+            Some(chunk) => chunk.sky_light[y as usize][z.rem_euclid(size) as usize][x.rem_euclid(size) as usize],
+            None => MAX_LIGHT,
+        }
+    }
+
+    /// Block light (red, green, blue) at chunk-local coordinates like `voxel`
+    pub fn block_light(&self, x: i32, y: i32, z: i32) -> [u8; 3] {
+        if y < 0 || y >= TOTAL_HEIGHT as i32 {
+            return [0; 3];
+        }
+        let size = CHUNK_SIZE as i32;
+        self.get(x.div_euclid(size), z.div_euclid(size))
+            .and_then(|chunk| chunk.block_light.as_ref())
+            .map_or([0; 3], |grid| unpack_rgb(grid[y as usize][z.rem_euclid(size) as usize][x.rem_euclid(size) as usize]))
+    }
+}
+
+/// Run one generation stage on the center chunk of `area` and return the new chunk.
+/// The mesh stage is not handled here, it needs the render world.
+pub(crate) fn run_stage(generator: &dyn ChunkGenerator, stage: ChunkStatus, area: &ChunkNeighborhood) -> Chunk {
+    match stage {
+        ChunkStatus::Noise => generator.generate(area.chunk_x, area.chunk_z),
+        ChunkStatus::Surface | ChunkStatus::Carvers | ChunkStatus::Features => {
+            let mut chunk = area.center().clone();
+            generator.run_stage(stage, &mut chunk, area);
+            chunk
+        }
+        ChunkStatus::Light => {
+            let volume = LightVolume::new(area);
+            let mut chunk = area.center().clone();
+            chunk.sky_light = volume.sky_light();
+            chunk.block_light = volume.block_light();
+            chunk
+        }
+        ChunkStatus::Empty | ChunkStatus::Mesh => area.center().clone(),
+    }
+}
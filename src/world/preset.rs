@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::config::load_toml_or_default;
+use crate::world::decoration::{default_decorations, DecorationConfig};
+use crate::world::dungeon::DungeonConfig;
+use crate::world::erosion::ErosionConfig;
+use crate::world::ore::{default_ores, OreConfig};
+use crate::world::river::RiverConfig;
+use crate::world::terrain::LakeConfig;
+
+pub const PRESET_PATH: &str = "world_preset.toml";
+
+/// Tunable world generation settings, read from `world_preset.toml`
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldPreset {
+    /// World y of the water surface, everything lower gets flooded
+    pub sea_level: i32,
+    /// Columns up to this many blocks above sea level get a sand beach
+    pub beach_height: i32,
+    pub lakes: LakeConfig,
+    pub rivers: RiverConfig,
+    /// Optional hydraulic erosion of the noise heightmap
+    pub erosion: ErosionConfig,
+    pub ores: Vec<OreConfig>,
+    pub decorations: Vec<DecorationConfig>,
+    pub dungeons: DungeonConfig,
+}
+
+impl Default for WorldPreset {
+    fn default() -> Self {
+        Self {
+            sea_level: 22,
+            beach_height: 2,
+            lakes: LakeConfig::default(),
+            rivers: RiverConfig::default(),
+            erosion: ErosionConfig::default(),
+            ores: default_ores(),
+            decorations: default_decorations(),
+            dungeons: DungeonConfig::default(),
+        }
+    }
+}
+
+impl WorldPreset {
+    pub fn load() -> Self {
+        load_toml_or_default(PRESET_PATH)
+    }
+}
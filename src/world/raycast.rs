@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+
+use crate::world::chunk_manager::ChunkManager;
+use crate::world::constants::{HEIGHT_BELOW, TOTAL_HEIGHT};
+use crate::world::voxel::Voxel;
+
+/// Block a ray stopped at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub block: IVec3,
+    /// Normal of the face the ray went in through, zero if it started inside the block
+    pub normal: IVec3,
+    /// Distance from the origin to where the ray enters the block
+    pub distance: f32,
+    pub voxel: Voxel,
+}
+
+/// Why a ray stopped
+enum Stop {
+    Hit(RaycastHit),
+    Unloaded,
+}
+
+/// Block at `pos`, air above and below the world in loaded columns.
+/// `None` only where the column isn't loaded.
+fn ray_block(chunk_manager: &ChunkManager, pos: IVec3) -> Option<Voxel> {
+    let bottom = -(HEIGHT_BELOW as i32);
+    let top = bottom + TOTAL_HEIGHT as i32 - 1;
+    if pos.y < bottom || pos.y > top {
+        return chunk_manager.voxel(pos.with_y(pos.y.clamp(bottom, top))).map(|_| Voxel::Air);
+    }
+    chunk_manager.voxel(pos)
+}
+
+/// Walk the blocks along a ray in order (Amanatides & Woo) until `stops` accepts one.
+/// `None` if nothing is hit within `max_distance`.
+fn cast(chunk_manager: &ChunkManager, origin: Vec3, direction: Vec3, max_distance: f32, stops: impl Fn(Voxel) -> bool) -> Option<Stop> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut block = origin.floor().as_ivec3();
+    let step = direction.signum().as_ivec3();
+    // Ray length per block along each axis, and to the first boundary
+    let delta = direction.abs().recip();
+    let mut next = Vec3::ZERO;
+    for axis in 0..3 {
+        next[axis] = if direction[axis] > 0.0 {
+            (block[axis] as f32 + 1.0 - origin[axis]) * delta[axis]
+        } else if direction[axis] < 0.0 {
+            (origin[axis] - block[axis] as f32) * delta[axis]
+        } else {
+            f32::INFINITY
+        };
+    }
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        let Some(voxel) = ray_block(chunk_manager, block) else {
+            return Some(Stop::Unloaded);
+        };
+        if stops(voxel) {
+            return Some(Stop::Hit(RaycastHit { block, normal, distance, voxel }));
+        }
+
+        // Step over the nearest block boundary
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+        distance = next[axis];
+        next[axis] += delta[axis];
+        block[axis] += step[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+    None
+}
+
+/// First solid block along a ray through loaded chunks, for picking.
+/// Stops without a hit at chunks that aren't loaded.
+pub fn raycast(chunk_manager: &ChunkManager, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RaycastHit> {
+    match cast(chunk_manager, origin, direction, max_distance, Voxel::is_solid)? {
+        Stop::Hit(hit) => Some(hit),
+        Stop::Unloaded => None,
+    }
+}
+
+/// Whether `to` can be seen from `from`: no block hides it and everything between is loaded
+pub fn line_of_sight(chunk_manager: &ChunkManager, from: Vec3, to: Vec3) -> bool {
+    let offset = to - from;
+    // The block `to` is in doesn't hide itself
+    let target = to.floor().as_ivec3();
+    cast(chunk_manager, from, offset, offset.length(), |voxel| !voxel.is_transparent())
+        .is_none_or(|stop| matches!(stop, Stop::Hit(hit) if hit.block == target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loaded columns from -20 to 39 on x and z, solid blocks at `blocks`
+    fn world(blocks: &[IVec3]) -> ChunkManager {
+        let mut chunk_manager = ChunkManager::empty_world(1);
+        for &pos in blocks {
+            assert!(chunk_manager.set_voxel(pos, Voxel::Solid));
+        }
+        chunk_manager
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_first_block() {
+        let chunk_manager = world(&[IVec3::new(5, 10, 0), IVec3::new(8, 10, 0)]);
+        let hit = raycast(&chunk_manager, Vec3::new(0.5, 10.5, 0.5), Vec3::X, 20.0).unwrap();
+        assert_eq!(hit.block, IVec3::new(5, 10, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert_eq!(hit.distance, 4.5);
+        assert_eq!(hit.voxel, Voxel::Solid);
+
+        let chunk_manager = world(&[IVec3::new(2, 3, 2)]);
+        let hit = raycast(&chunk_manager, Vec3::new(2.5, 10.5, 2.5), Vec3::NEG_Y, 20.0).unwrap();
+        assert_eq!(hit.block, IVec3::new(2, 3, 2));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.distance, 6.5);
+    }
+
+    #[test]
+    fn diagonal_ray_crosses_chunk_borders() {
+        // Goes from chunk (0, 0) over the corner into chunk (1, 1)
+        let chunk_manager = world(&[IVec3::new(22, 5, 22)]);
+        let hit = raycast(&chunk_manager, Vec3::new(18.2, 5.5, 18.6), Vec3::new(1.0, 0.0, 1.0), 20.0).unwrap();
+        assert_eq!(hit.block, IVec3::new(22, 5, 22));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 3.8 * std::f32::consts::SQRT_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ray_through_negative_coordinates() {
+        // Into chunk (-1, -1) and below y = 0
+        let chunk_manager = world(&[IVec3::new(-4, -6, -3)]);
+        let hit = raycast(&chunk_manager, Vec3::new(1.3, -5.5, 1.7), Vec3::new(-1.0, 0.0, -1.0), 20.0).unwrap();
+        assert_eq!(hit.block, IVec3::new(-4, -6, -3));
+        assert_eq!(hit.normal, IVec3::X);
+        assert!((hit.distance - 4.3 * std::f32::consts::SQRT_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn nothing_hit_past_max_distance() {
+        let chunk_manager = world(&[IVec3::new(5, 10, 0)]);
+        let origin = Vec3::new(0.5, 10.5, 0.5);
+        assert_eq!(raycast(&chunk_manager, origin, Vec3::X, 4.0), None);
+        assert!(raycast(&chunk_manager, origin, Vec3::X, 4.5).is_some());
+    }
+
+    #[test]
+    fn hit_reports_the_face_the_ray_entered() {
+        let block = IVec3::new(0, 10, 0);
+        let chunk_manager = world(&[block]);
+        let center = block.as_vec3() + Vec3::splat(0.5);
+        for direction in [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z] {
+            let origin = center - direction.as_vec3() * 3.0;
+            let hit = raycast(&chunk_manager, origin, direction.as_vec3(), 10.0).unwrap();
+            assert_eq!(hit.block, block);
+            assert_eq!(hit.normal, -direction);
+            assert_eq!(hit.distance, 2.5);
+        }
+
+        // Starting inside the block there is no face
+        let hit = raycast(&chunk_manager, center, Vec3::X, 10.0).unwrap();
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn ray_stops_at_unloaded_chunks() {
+        let chunk_manager = world(&[]);
+        assert_eq!(raycast(&chunk_manager, Vec3::new(0.5, 10.5, 0.5), Vec3::X, 100.0), None);
+    }
+
+    #[test]
+    fn line_of_sight_is_blocked_by_opaque_blocks_only() {
+        let mut chunk_manager = world(&[IVec3::new(5, 10, 0)]);
+        let from = Vec3::new(0.5, 10.5, 0.5);
+        assert!(!line_of_sight(&chunk_manager, from, Vec3::new(9.5, 10.5, 0.5)));
+        // The target block doesn't hide itself
+        assert!(line_of_sight(&chunk_manager, from, Vec3::new(5.5, 10.5, 0.5)));
+        assert!(line_of_sight(&chunk_manager, from, Vec3::new(0.5, 10.5, 9.5)));
+
+        chunk_manager.set_voxel(IVec3::new(5, 10, 0), Voxel::Water);
+        assert!(line_of_sight(&chunk_manager, from, Vec3::new(9.5, 10.5, 0.5)));
+
+        // Nothing is seen through chunks that aren't loaded
+        assert!(!line_of_sight(&chunk_manager, from, Vec3::new(50.5, 10.5, 0.5)));
+    }
+}
@@ -0,0 +1,89 @@
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+/// Rivers follow the zero crossing of a low frequency noise field, so they are
+/// continuous lines that don't care about chunk borders
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiverConfig {
+    pub enabled: bool,
+    /// Noise frequency, lower = longer and fewer rivers
+    pub frequency: f64,
+    /// Half width of the water channel, in noise units
+    pub channel_width: f64,
+    /// Half width of the valley carved around the channel, in noise units
+    pub valley_width: f64,
+    /// How far the river bed goes below sea level in the middle of the channel
+    pub depth: i32,
+}
+
+impl Default for RiverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frequency: 0.0025,
+            channel_width: 0.025,
+            valley_width: 0.12,
+            depth: 3,
+        }
+    }
+}
+
+/// River at one column
+#[derive(Clone, Copy, Debug)]
+pub struct RiverSample {
+    /// 0 in the middle of the channel, 1 at the outer edge of the valley
+    pub distance: f32,
+    /// Inside the water channel itself, not just the valley
+    pub in_channel: bool,
+}
+
+impl RiverSample {
+    /// How strongly the terrain gets pulled down into the valley (0..1)
+    pub fn valley_factor(&self) -> f32 {
+        let t = 1.0 - self.distance;
+        t * t * (3.0 - 2.0 * t) // smoothstep
+    }
+}
+
+pub struct RiverMap {
+    noise: Perlin,
+    config: RiverConfig,
+}
+
+impl RiverMap {
+    pub fn new(noise_seed: u32, config: &RiverConfig) -> Self {
+        Self {
+            noise: Perlin::new(noise_seed),
+            config: config.clone(),
+        }
+    }
+
+    /// River valley at a column, `None` away from any river
+    pub fn sample(&self, world_x: i32, world_z: i32) -> Option<RiverSample> {
+        if !self.config.enabled || self.config.valley_width <= 0.0 {
+            return None;
+        }
+        let freq = self.config.frequency;
+        let n = self.noise.get([world_x as f64 * freq, world_z as f64 * freq]).abs();
+        if n >= self.config.valley_width {
+            return None;
+        }
+
+        Some(RiverSample {
+            distance: (n / self.config.valley_width) as f32,
+            in_channel: n < self.config.channel_width,
+        })
+    }
+
+    pub fn is_channel(&self, world_x: i32, world_z: i32) -> bool {
+        self.sample(world_x, world_z).is_some_and(|r| r.in_channel)
+    }
+
+    /// Depth of the river bed below sea level at this point of the channel
+    pub fn bed_depth(&self, sample: &RiverSample) -> i32 {
+        let channel = (self.config.channel_width / self.config.valley_width) as f32;
+        let t = 1.0 - (sample.distance / channel).min(1.0);
+        1 + (self.config.depth as f32 * t).round() as i32
+    }
+}
@@ -0,0 +1,341 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::world::constants::CHUNK_SIZE;
+use crate::world::decoration::ChunkWriter;
+use crate::world::seed::{chunk_rng, mix64};
+use crate::world::terrain::Terrain;
+use crate::world::voxel::{Chunk, Voxel};
+
+pub const STRUCTURES_DIR: &str = "assets/structures";
+
+const STRUCTURE_SALT: u64 = 0x7374_7275_6374; // "struct"
+
+/// Where and how often a structure may appear
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlacementRules {
+    /// Size of the placement grid in chunks, at most one structure per grid cell
+    pub spacing: u32,
+    /// Minimum distance in chunks between two structures of neighbouring cells
+    pub separation: u32,
+    /// Keeps different structures with the same spacing from lining up
+    pub salt: u64,
+    /// Surface blocks the structure may stand on, empty = any.
+    /// Stands in for biome rules until the world has biomes.
+    pub surface_blocks: Vec<Voxel>,
+    pub allow_water: bool,
+    pub allow_rivers: bool,
+    /// Added to the ground height, negative values sink the structure
+    pub y_offset: i32,
+    pub rotate: bool,
+    pub mirror: bool,
+}
+
+impl Default for PlacementRules {
+    fn default() -> Self {
+        Self {
+            spacing: 8,
+            separation: 3,
+            salt: 0,
+            surface_blocks: Vec::new(),
+            allow_water: false,
+            allow_rivers: false,
+            y_offset: 0,
+            rotate: true,
+            mirror: true,
+        }
+    }
+}
+
+/// Template file as written on disk.
+/// `layers` go bottom to top, each layer is a list of rows along +z and each
+/// character of a row is one block along +x, looked up in `palette`.
+/// Characters missing from the palette leave the world untouched.
+#[derive(Deserialize)]
+struct TemplateFile {
+    name: String,
+    /// Block of the template (x, y, z) that lands on the placement position
+    #[serde(default)]
+    origin: [i32; 3],
+    palette: HashMap<char, Voxel>,
+    layers: Vec<Vec<String>>,
+    #[serde(default)]
+    placement: PlacementRules,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [Rotation::None, Rotation::Clockwise90, Rotation::Clockwise180, Rotation::Clockwise270];
+
+    /// Rotate an (x, z) offset around the y axis
+    pub fn apply(self, x: i32, z: i32) -> (i32, i32) {
+        match self {
+            Rotation::None => (x, z),
+            Rotation::Clockwise90 => (-z, x),
+            Rotation::Clockwise180 => (-x, -z),
+            Rotation::Clockwise270 => (z, -x),
+        }
+    }
+}
+
+/// Prebuilt voxel grid with a palette already resolved to blocks
+pub struct StructureTemplate {
+    pub name: String,
+    pub size: IVec3,
+    pub origin: IVec3,
+    /// `None` = structure void, keep whatever is there
+    blocks: Vec<Option<Voxel>>,
+    pub placement: PlacementRules,
+}
+
+impl StructureTemplate {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let file: TemplateFile = toml::from_str(text).map_err(|err| err.to_string())?;
+
+        let size_y = file.layers.len() as i32;
+        let size_z = file.layers.iter().map(|layer| layer.len()).max().unwrap_or(0) as i32;
+        let size_x = file
+            .layers
+            .iter()
+            .flatten()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0) as i32;
+        if size_x == 0 || size_y == 0 || size_z == 0 {
+            return Err(format!("structure '{}' is empty", file.name));
+        }
+
+        let size = IVec3::new(size_x, size_y, size_z);
+        let mut blocks = vec![None; (size_x * size_y * size_z) as usize];
+        for (y, layer) in file.layers.iter().enumerate() {
+            for (z, row) in layer.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    let i = (y as i32 * size_z + z as i32) * size_x + x as i32;
+                    blocks[i as usize] = file.palette.get(&c).copied();
+                }
+            }
+        }
+
+        Ok(Self {
+            name: file.name,
+            size,
+            origin: IVec3::from_array(file.origin),
+            blocks,
+            placement: file.placement,
+        })
+    }
+
+    pub fn get(&self, local: IVec3) -> Option<Voxel> {
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(self.size).any() {
+            return None;
+        }
+        self.blocks[((local.y * self.size.z + local.z) * self.size.x + local.x) as usize]
+    }
+
+    /// Furthest horizontal distance from the origin in any rotation
+    pub fn reach(&self) -> i32 {
+        let far = self.size - self.origin - IVec3::ONE;
+        self.origin.x.max(self.origin.z).max(far.x).max(far.z)
+    }
+
+    /// Write the template with its origin at `pos`, clipped to the writer's chunk
+    pub fn place(&self, pos: IVec3, rotation: Rotation, mirror: bool, out: &mut ChunkWriter) {
+        for y in 0..self.size.y {
+            for z in 0..self.size.z {
+                for x in 0..self.size.x {
+                    let Some(block) = self.get(IVec3::new(x, y, z)) else {
+                        continue;
+                    };
+                    let mut dx = x - self.origin.x;
+                    if mirror {
+                        dx = -dx;
+                    }
+                    let (rx, rz) = rotation.apply(dx, z - self.origin.z);
+                    out.set(pos + IVec3::new(rx, y - self.origin.y, rz), block);
+                }
+            }
+        }
+    }
+}
+
+/// Read every `*.toml` template in `dir`, broken files are skipped with a warning
+pub fn load_structures(dir: &str) -> Vec<StructureTemplate> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    // Directory order isn't stable, the template order feeds into placement
+    paths.sort();
+
+    let mut structures = Vec::new();
+    for path in paths {
+        match fs::read_to_string(&path).map_err(|err| err.to_string()).and_then(|text| StructureTemplate::from_toml(&text)) {
+            Ok(template) => {
+                info!("Loaded structure '{}' from {}", template.name, path.display());
+                structures.push(template);
+            }
+            Err(err) => warn!("Failed to load structure {}: {}", path.display(), err),
+        }
+    }
+    structures
+}
+
+/// Place every structure that reaches into this chunk.
+/// Each grid cell of a template's placement picks one start chunk from its own seed,
+/// so all chunks a structure overlaps agree on where it is and only write their part.
+pub fn place_structures(
+    chunk: &mut Chunk,
+    chunk_x: i32,
+    chunk_z: i32,
+    seed: u64,
+    terrain: &Terrain,
+    structures: &[StructureTemplate],
+) {
+    let mut out = ChunkWriter::new(chunk, chunk_x, chunk_z);
+
+    for template in structures {
+        let rules = &template.placement;
+        let spacing = rules.spacing.max(1) as i32;
+        let separation = (rules.separation as i32).min(spacing - 1);
+        let reach = (template.reach() + CHUNK_SIZE as i32 - 1) / CHUNK_SIZE as i32;
+
+        let min_cell_x = (chunk_x - reach).div_euclid(spacing);
+        let max_cell_x = (chunk_x + reach).div_euclid(spacing);
+        let min_cell_z = (chunk_z - reach).div_euclid(spacing);
+        let max_cell_z = (chunk_z + reach).div_euclid(spacing);
+
+        for cell_x in min_cell_x..=max_cell_x {
+            for cell_z in min_cell_z..=max_cell_z {
+                let mut rng = chunk_rng(seed, cell_x, cell_z, STRUCTURE_SALT ^ mix64(rules.salt));
+
+                // Draw everything up front so the stream never depends on the target chunk
+                let start_x = cell_x * spacing + rng.random_range(0..spacing - separation);
+                let start_z = cell_z * spacing + rng.random_range(0..spacing - separation);
+                let local_x = rng.random_range(0..CHUNK_SIZE as i32);
+                let local_z = rng.random_range(0..CHUNK_SIZE as i32);
+                let rotation = Rotation::ALL[rng.random_range(0..4)];
+                let mirror = rng.random_bool(0.5);
+
+                if (start_x - chunk_x).abs() > reach || (start_z - chunk_z).abs() > reach {
+                    continue;
+                }
+
+                let world_x = start_x * CHUNK_SIZE as i32 + local_x;
+                let world_z = start_z * CHUNK_SIZE as i32 + local_z;
+                let column = terrain.column(world_x, world_z);
+
+                if column.water_level.is_some() && !rules.allow_water {
+                    continue;
+                }
+                if !rules.allow_rivers && terrain.rivers().is_channel(world_x, world_z) {
+                    continue;
+                }
+                if !rules.surface_blocks.is_empty() && !rules.surface_blocks.contains(&column.top) {
+                    continue;
+                }
+
+                let pos = IVec3::new(world_x, column.surface + 1 + rules.y_offset, world_z);
+                template.place(
+                    pos,
+                    if rules.rotate { rotation } else { Rotation::None },
+                    rules.mirror && mirror,
+                    &mut out,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::constants::HEIGHT_BELOW;
+    use crate::world::preset::WorldPreset;
+
+    const SEED: u64 = 12345;
+
+    /// A wall longer than a chunk, so every placement crosses a chunk border
+    const WALL: &str = r#"
+        name = "wall"
+        origin = [12, 0, 0]
+        layers = [["GGGGGGGGGGGGGGGGGGGGGGGGG"], ["GGGGGGGGGGGGGGGGGGGGGGGGG"]]
+
+        [palette]
+        "G" = "glowstone"
+
+        [placement]
+        spacing = 2
+        separation = 0
+        allow_water = true
+        allow_rivers = true
+    "#;
+
+    /// Chunks (-2, -2) to (1, 1), placed in the order given, sharing one terrain
+    fn place_all(order: &[(i32, i32)]) -> HashMap<(i32, i32), Box<Chunk>> {
+        let terrain = Terrain::new(SEED, &WorldPreset::default(), None);
+        let structures = [StructureTemplate::from_toml(WALL).unwrap()];
+        order
+            .iter()
+            .map(|&(chunk_x, chunk_z)| {
+                let mut chunk = Box::new(Chunk::empty());
+                place_structures(&mut chunk, chunk_x, chunk_z, SEED, &terrain, &structures);
+                ((chunk_x, chunk_z), chunk)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn structures_are_the_same_in_any_chunk_order() {
+        let mut order: Vec<_> = (-2..=1).flat_map(|x| (-2..=1).map(move |z| (x, z))).collect();
+        let forward = place_all(&order);
+        order.reverse();
+        let backward = place_all(&order);
+
+        for (key, chunk) in &forward {
+            assert!(chunk.data == backward[key].data, "chunk {key:?} differs");
+        }
+
+        // Not vacuous, walls did get placed
+        let walled = forward
+            .values()
+            .filter(|chunk| chunk.data.iter().flatten().flatten().any(|&v| v == Voxel::Glowstone as u8))
+            .count();
+        assert!(walled > 1, "only {walled} chunks got a wall");
+    }
+
+    #[test]
+    fn template_is_clipped_to_each_chunk_and_lines_up() {
+        let template = StructureTemplate::from_toml(WALL).unwrap();
+        // Origin in chunk 0, the wall reaches back into chunk -1 and on into chunk 1
+        let pos = IVec3::new(5, 10, 3);
+        let mut chunks: Vec<_> = (-1..=1).map(|_| Box::new(Chunk::empty())).collect();
+        for (chunk, chunk_x) in chunks.iter_mut().zip(-1..=1) {
+            template.place(pos, Rotation::None, false, &mut ChunkWriter::new(chunk, chunk_x, 0));
+        }
+
+        for x in pos.x - 12..=pos.x + 12 {
+            for y in pos.y..pos.y + 2 {
+                let chunk_x = x.div_euclid(CHUNK_SIZE as i32);
+                let chunk = &chunks[(chunk_x + 1) as usize];
+                let local_x = x.rem_euclid(CHUNK_SIZE as i32) as usize;
+                let voxel = chunk.data[(y + HEIGHT_BELOW as i32) as usize][pos.z as usize][local_x];
+                assert_eq!(voxel, Voxel::Glowstone as u8, "missing wall block at x = {x}");
+            }
+        }
+    }
+}
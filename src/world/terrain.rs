@@ -0,0 +1,305 @@
+use noise::{NoiseFn, Perlin};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::world::constants::{CHUNK_SIZE, HEIGHT_ABOVE, HEIGHT_BELOW, TOTAL_HEIGHT};
+use crate::world::erosion::ErosionCache;
+use crate::world::preset::WorldPreset;
+use crate::world::river::{RiverMap, RiverSample};
+use crate::world::seed::{chunk_rng, noise_seed};
+use crate::world::voxel::{Chunk, Voxel};
+
+const HEIGHT_SALT: u64 = 0x6865_6967_6874; // "height"
+const CAVE_SALT: u64 = 0x6361_7665; // "cave"
+const RIVER_SALT: u64 = 0x7269_7665_72; // "river"
+const LAKE_SALT: u64 = 0x6C61_6B65; // "lake"
+
+/// How many blocks of sand lie on top of the stone on beaches and water beds
+pub const SAND_DEPTH: i32 = 3;
+
+/// Lakes are placed at most one per grid cell, inside local depressions
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LakeConfig {
+    pub enabled: bool,
+    /// Size of the lake grid cells in blocks
+    pub cell_size: u32,
+    /// Chance for a cell to get a lake
+    pub chance: f32,
+    pub min_radius: u32,
+    pub max_radius: u32,
+    /// Depth of the lake bowl in its middle
+    pub depth: u32,
+}
+
+impl Default for LakeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cell_size: 96,
+            chance: 0.35,
+            min_radius: 8,
+            max_radius: 18,
+            depth: 5,
+        }
+    }
+}
+
+/// One lake, everything inside `radius` that's below `water_level` gets flooded
+#[derive(Clone, Copy, Debug)]
+struct Lake {
+    center_x: i32,
+    center_z: i32,
+    radius: i32,
+    water_level: i32,
+    depth: i32,
+}
+
+/// Final shape of one column after all surface rules
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    /// World y of the topmost solid block
+    pub surface: i32,
+    /// World y of the water surface, if the column is flooded
+    pub water_level: Option<i32>,
+    /// Block used for the top `SAND_DEPTH` layers
+    pub top: Voxel,
+    /// River valley this column lies in, if any
+    pub river: Option<RiverSample>,
+}
+
+impl Default for Column {
+    fn default() -> Self {
+        Self { surface: 0, water_level: None, top: Voxel::Solid, river: None }
+    }
+}
+
+/// Base terrain noise, shared by chunk filling and everything that needs to know
+/// the ground height of columns outside the chunk being generated
+pub struct Terrain {
+    seed: u64,
+    perlin: Perlin,
+    cave_noise: Perlin,
+    rivers: RiverMap,
+    sea_level: i32,
+    beach_height: i32,
+    lakes: LakeConfig,
+    lake_cache: RefCell<HashMap<(i32, i32), Option<Lake>>>,
+    erosion: Option<Arc<ErosionCache>>,
+}
+
+impl Terrain {
+    const FREQ: f64 = 0.01;
+    const CAVE_FREQ: f64 = 0.1;
+
+    pub fn new(seed: u64, preset: &WorldPreset, erosion: Option<Arc<ErosionCache>>) -> Self {
+        Self {
+            seed,
+            // Perlin noise for terrain height
+            perlin: Perlin::new(noise_seed(seed, HEIGHT_SALT)),
+            // Cave noise
+            cave_noise: Perlin::new(noise_seed(seed, CAVE_SALT)),
+            rivers: RiverMap::new(noise_seed(seed, RIVER_SALT), &preset.rivers),
+            sea_level: preset.sea_level,
+            beach_height: preset.beach_height,
+            lakes: preset.lakes.clone(),
+            lake_cache: RefCell::new(HashMap::new()),
+            erosion,
+        }
+    }
+
+    /// Raw noise height of a column, before erosion and water carving
+    pub fn base_height(&self, world_x: i32, world_z: i32) -> f32 {
+        let world_x = world_x as f64;
+        let world_z = world_z as f64;
+        let freq = Self::FREQ;
+
+        // Smooth terrain with 5-sample blur
+        let center = self.perlin.get([world_x * freq, world_z * freq]);
+        let nx = self.perlin.get([(world_x + 1.0) * freq, world_z * freq]);
+        let px = self.perlin.get([(world_x - 1.0) * freq, world_z * freq]);
+        let nz = self.perlin.get([world_x * freq, (world_z + 1.0) * freq]);
+        let pz = self.perlin.get([world_x * freq, (world_z - 1.0) * freq]);
+        let height_noise = (center + nx + px + nz + pz) / 5.0;
+
+        ((height_noise + 1.0) * 0.5 * HEIGHT_ABOVE as f64) as f32
+    }
+
+    /// World y of the topmost solid block of a column (0..HEIGHT_ABOVE), before water carving
+    pub fn surface_height(&self, world_x: i32, world_z: i32) -> usize {
+        let h = match &self.erosion {
+            Some(erosion) => erosion.height(self, world_x, world_z).max(0.0),
+            None => self.base_height(world_x, world_z),
+        };
+        (h as usize).min(HEIGHT_ABOVE - 1)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Surface, water and top block of a column
+    pub fn column(&self, world_x: i32, world_z: i32) -> Column {
+        let mut surface = self.surface_height(world_x, world_z) as i32;
+        let mut water_level = None;
+
+        // Rivers pull the terrain down to just above sea level, the channel cuts below it
+        let river = self.rivers.sample(world_x, world_z);
+        if let Some(river) = river {
+            let bank = self.sea_level + 1;
+            let drop = ((surface - bank).max(0) as f32 * river.valley_factor()).round() as i32;
+            surface -= drop;
+            if river.in_channel {
+                surface = surface.min(self.sea_level - self.rivers.bed_depth(&river)).max(0);
+            }
+        }
+
+        let lake = if river.is_some_and(|r| r.in_channel) { None } else { self.lake_at(world_x, world_z) };
+        if let Some(lake) = lake {
+            let dx = (world_x - lake.center_x) as f32;
+            let dz = (world_z - lake.center_z) as f32;
+            let t = (dx * dx + dz * dz) / (lake.radius * lake.radius) as f32;
+            // Only ground under the water gets dug out, higher columns stay as the shore
+            if t < 1.0 && surface <= lake.water_level {
+                // Bowl shape, deepest in the middle
+                let bed = lake.water_level - (lake.depth as f32 * (1.0 - t)).round() as i32;
+                surface = surface.min(bed.max(0));
+                if surface < lake.water_level {
+                    water_level = Some(lake.water_level);
+                }
+            }
+        }
+
+        if surface < self.sea_level {
+            // Lakes always sit above sea level
+            water_level = Some(water_level.unwrap_or(self.sea_level));
+        }
+
+        let top = if water_level.is_some() || surface <= self.sea_level + self.beach_height {
+            Voxel::Sand
+        } else {
+            Voxel::Solid
+        };
+
+        Column { surface, water_level, top, river }
+    }
+
+    /// River channel lookup for biome and decoration code
+    pub fn rivers(&self) -> &RiverMap {
+        &self.rivers
+    }
+
+    /// Underground (world_y < 0) density check, false = cave air
+    pub fn is_cave_solid(&self, world_x: i32, world_y: i32, world_z: i32) -> bool {
+        let freq = Self::CAVE_FREQ;
+        let n = self.cave_noise.get([world_x as f64 * freq, world_y as f64 * freq, world_z as f64 * freq]);
+        n > 0.3
+    }
+
+    /// Noise stage: stone up to the surface and caves below world y 0
+    pub fn fill_density(&self, chunk: &mut Chunk, chunk_x: i32, chunk_z: i32) {
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let world_x = chunk_x * CHUNK_SIZE as i32 + x as i32;
+                let world_z = chunk_z * CHUNK_SIZE as i32 + z as i32;
+                let surface = self.column(world_x, world_z).surface;
+
+                for y in 0..TOTAL_HEIGHT {
+                    let world_y = y as i32 - HEIGHT_BELOW as i32;
+                    let solid = if world_y < 0 {
+                        self.is_cave_solid(world_x, world_y, world_z)
+                    } else {
+                        world_y <= surface
+                    };
+                    if solid {
+                        chunk.data[y][z][x] = Voxel::Solid as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Surface stage: sand on beaches and water beds, then the water itself
+    pub fn fill_surface(&self, chunk: &mut Chunk, chunk_x: i32, chunk_z: i32) {
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let column = self.column(chunk_x * CHUNK_SIZE as i32 + x as i32, chunk_z * CHUNK_SIZE as i32 + z as i32);
+
+                // Sand only a few blocks deep
+                for world_y in (column.surface - SAND_DEPTH + 1).max(0)..=column.surface {
+                    chunk.data[(world_y + HEIGHT_BELOW as i32) as usize][z][x] = column.top as u8;
+                }
+                if let Some(level) = column.water_level {
+                    for world_y in column.surface + 1..=level {
+                        chunk.data[(world_y + HEIGHT_BELOW as i32) as usize][z][x] = Voxel::Water as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    fn lake_at(&self, world_x: i32, world_z: i32) -> Option<Lake> {
+        if !self.lakes.enabled || self.lakes.cell_size == 0 {
+            return None;
+        }
+        let cell = self.lakes.cell_size as i32;
+        let key = (world_x.div_euclid(cell), world_z.div_euclid(cell));
+
+        *self
+            .lake_cache
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| self.lake_in_cell(key.0, key.1))
+    }
+
+    fn lake_in_cell(&self, cell_x: i32, cell_z: i32) -> Option<Lake> {
+        let cell = self.lakes.cell_size as i32;
+        let mut rng = chunk_rng(self.seed, cell_x, cell_z, LAKE_SALT);
+
+        if !rng.random_bool(self.lakes.chance.clamp(0.0, 1.0) as f64) {
+            return None;
+        }
+
+        // Keep the lake inside its cell so a column only has to look at one cell
+        let max_radius = (self.lakes.max_radius as i32).min(cell / 2 - 1);
+        let min_radius = (self.lakes.min_radius as i32).min(max_radius);
+        if max_radius < 2 {
+            return None;
+        }
+        let radius = rng.random_range(min_radius..=max_radius);
+        let center_x = cell_x * cell + rng.random_range(radius..cell - radius);
+        let center_z = cell_z * cell + rng.random_range(radius..cell - radius);
+
+        // Water fills up to the lowest point of the rim, so it never spills out
+        let rim = (0..8)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::TAU / 8.0;
+                let x = center_x + (angle.cos() * radius as f32) as i32;
+                let z = center_z + (angle.sin() * radius as f32) as i32;
+                self.surface_height(x, z) as i32
+            })
+            .min()?;
+        let water_level = rim - 1;
+
+        // Only in a depression: the middle has to be under water
+        if self.surface_height(center_x, center_z) as i32 > water_level {
+            return None;
+        }
+
+        // Below sea level the ocean takes care of it
+        if water_level <= self.sea_level {
+            return None;
+        }
+
+        Some(Lake {
+            center_x,
+            center_z,
+            radius,
+            water_level,
+            depth: self.lakes.depth as i32,
+        })
+    }
+}
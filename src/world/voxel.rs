@@ -1,18 +1,106 @@
-use noise::{NoiseFn, Perlin};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use crate::world::constants::{CHUNK_SIZE, HEIGHT_ABOVE, HEIGHT_BELOW, TOTAL_HEIGHT};
+use serde::{Deserialize, Serialize};
+use crate::world::constants::{CHUNK_SIZE, TOTAL_HEIGHT};
+use crate::world::lighting::{BlockLightGrid, LightGrid};
 use bevy::prelude::*;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
 pub enum Voxel {
     Air,
     Solid,
+    CoalOre,
+    IronOre,
+    GoldOre,
+    DiamondOre,
+    Log,
+    Leaves,
+    Planks,
+    Water,
+    Sand,
+    Torch,
+    Lava,
+    Glowstone,
+}
+
+impl Voxel {
+    pub const ALL: [Voxel; 14] = [
+        Voxel::Air,
+        Voxel::Solid,
+        Voxel::CoalOre,
+        Voxel::IronOre,
+        Voxel::GoldOre,
+        Voxel::DiamondOre,
+        Voxel::Log,
+        Voxel::Leaves,
+        Voxel::Planks,
+        Voxel::Water,
+        Voxel::Sand,
+        Voxel::Torch,
+        Voxel::Lava,
+        Voxel::Glowstone,
+    ];
+
+    /// Decode a value from `Chunk::data`, unknown ids become air
+    pub fn from_u8(id: u8) -> Self {
+        Self::ALL.get(id as usize).copied().unwrap_or(Voxel::Air)
+    }
+
+    /// Blocks that don't hide the faces behind them
+    pub fn is_transparent(self) -> bool {
+        matches!(self, Voxel::Air | Voxel::Water)
+    }
+
+    /// Blocks the player can't walk through
+    pub fn is_solid(self) -> bool {
+        !matches!(self, Voxel::Air | Voxel::Water | Voxel::Lava)
+    }
+
+    /// Blocks x-ray mode shows through the stone: ores and anything placed by structures
+    pub fn is_xray_highlight(self) -> bool {
+        matches!(
+            self,
+            Voxel::CoalOre
+                | Voxel::IronOre
+                | Voxel::GoldOre
+                | Voxel::DiamondOre
+                | Voxel::Planks
+                | Voxel::Torch
+                | Voxel::Lava
+                | Voxel::Glowstone
+        )
+    }
+
+    /// Vertex color (sRGB) used by the mesher
+    pub fn color(self) -> Color {
+        match self {
+            Voxel::Air => Color::NONE,
+            Voxel::Solid => Color::srgb(0.5, 0.8, 0.5),
+            Voxel::CoalOre => Color::srgb(0.15, 0.15, 0.15),
+            Voxel::IronOre => Color::srgb(0.75, 0.6, 0.5),
+            Voxel::GoldOre => Color::srgb(0.95, 0.8, 0.2),
+            Voxel::DiamondOre => Color::srgb(0.4, 0.9, 0.95),
+            Voxel::Log => Color::srgb(0.4, 0.28, 0.15),
+            Voxel::Leaves => Color::srgb(0.2, 0.5, 0.15),
+            Voxel::Planks => Color::srgb(0.7, 0.55, 0.3),
+            Voxel::Water => Color::srgba(0.15, 0.35, 0.8, 0.6),
+            Voxel::Sand => Color::srgb(0.85, 0.8, 0.55),
+            Voxel::Torch => Color::srgb(1.0, 0.85, 0.4),
+            Voxel::Lava => Color::srgb(0.95, 0.4, 0.05),
+            Voxel::Glowstone => Color::srgb(0.95, 0.85, 0.5),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Chunk {
     pub data: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT],
+    /// Skylight level per voxel, filled in by the light stage
+    pub sky_light: LightGrid,
+    /// Colored light from emissive blocks, `None` while there is none
+    pub block_light: Option<Box<BlockLightGrid>>,
 }
 
 #[derive(Component)]
@@ -25,67 +113,3 @@ pub struct ChunkEntity {
 struct ChunkPendingDespawn {
     timer: Timer,
 }
-
-
-
-impl Chunk {
-    pub fn new(chunk_x: i32, chunk_z: i32, seed: u64) -> Self {
-        let mut data = [[[Voxel::Air as u8; CHUNK_SIZE]; CHUNK_SIZE]; TOTAL_HEIGHT];
-
-        // Perlin noise for terrain height
-        let perlin = Perlin::new(seed as u32);
-        let freq = 0.01;
-
-        // Cave noise
-        let cave_noise = Perlin::new((seed.wrapping_add(1)) as u32);
-        let cave_freq = 0.1;
-
-        // Precompute surface height map
-        let mut height_map = [[0usize; CHUNK_SIZE]; CHUNK_SIZE];
-        for x in 0..CHUNK_SIZE {
-            for z in 0..CHUNK_SIZE {
-                let world_x = (chunk_x * CHUNK_SIZE as i32 + x as i32) as f64;
-                let world_z = (chunk_z * CHUNK_SIZE as i32 + z as i32) as f64;
-
-                // Smooth terrain with 5-sample blur
-                let center = perlin.get([world_x * freq, world_z * freq]);
-                let nx = perlin.get([(world_x + 1.0) * freq, world_z * freq]);
-                let px = perlin.get([(world_x - 1.0) * freq, world_z * freq]);
-                let nz = perlin.get([world_x * freq, (world_z + 1.0) * freq]);
-                let pz = perlin.get([world_x * freq, (world_z - 1.0) * freq]);
-                let height_noise = (center + nx + px + nz + pz) / 5.0;
-
-                let h = ((height_noise + 1.0) * 0.5 * HEIGHT_ABOVE as f64) as usize;
-                height_map[x][z] = h.min(HEIGHT_ABOVE - 1);
-            }
-        }
-
-        // Fill chunk data
-        for x in 0..CHUNK_SIZE {
-            for z in 0..CHUNK_SIZE {
-                let surface_y = height_map[x][z];
-                let world_x = (chunk_x * CHUNK_SIZE as i32 + x as i32) as f64;
-                let world_z = (chunk_z * CHUNK_SIZE as i32 + z as i32) as f64;
-
-                for y in 0..TOTAL_HEIGHT {
-                    let world_y = y as i32 - HEIGHT_BELOW as i32;
-
-                    // Underground / cave generation
-                    if world_y < 0 {
-                        let n = cave_noise.get([world_x * cave_freq, world_y as f64 * cave_freq, world_z * cave_freq]);
-                        if n > 0.3 {
-                            data[y][z][x] = Voxel::Solid as u8;
-                        }
-                    } else {
-                        // Surface terrain
-                        if world_y as usize <= surface_y {
-                            data[y][z][x] = Voxel::Solid as u8;
-                        }
-                    }
-                }
-            }
-        }
-
-        Self { data }
-    }
-}